@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+use crate::vec3::Vec3;
+
+/// Values that can be interpolated between keyframes.
+pub trait Interpolate: Copy {
+    fn to_vec(&self) -> Vec3;
+    fn from_vec(v: &Vec3) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn to_vec(&self) -> Vec3 {
+        Vec3::new(*self, 0.0, 0.0)
+    }
+
+    fn from_vec(v: &Vec3) -> Self {
+        v.x()
+    }
+}
+
+impl Interpolate for [f64; 3] {
+    fn to_vec(&self) -> Vec3 {
+        Vec3::new(self[0], self[1], self[2])
+    }
+
+    fn from_vec(v: &Vec3) -> Self {
+        [v.x(), v.y(), v.z()]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Interpolation {
+    #[default]
+    #[serde(rename = "linear")]
+    Linear,
+    #[serde(rename = "catmull_rom")]
+    CatmullRom,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+}
+
+/// A value that is either fixed or keyframed over time.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Keyframed<T> {
+    Static(T),
+    Keyframes(Vec<Keyframe<T>>),
+}
+
+impl<T: Interpolate> Keyframed<T> {
+    /// Value at the given time, held constant before the first and after the
+    /// last keyframe.
+    pub fn at(&self, time: f64, interpolation: Interpolation) -> T {
+        let keys = match self {
+            Keyframed::Static(v) => return *v,
+            Keyframed::Keyframes(k) => {
+                let mut k: Vec<(f64, Vec3)> =
+                    k.iter().map(|k| (k.time, k.value.to_vec())).collect();
+                k.sort_by(|a, b| a.0.total_cmp(&b.0));
+                k
+            }
+        };
+
+        let i = keys.partition_point(|k| k.0 <= time);
+        if i == 0 {
+            return T::from_vec(&keys.first().map_or(Vec3::zero(), |k| k.1));
+        }
+        if i == keys.len() {
+            return T::from_vec(&keys[i - 1].1);
+        }
+
+        let (t0, p0) = keys[i - 1];
+        let (t1, p1) = keys[i];
+        let dt = t1 - t0;
+        let u = (time - t0) / dt;
+        let v = match interpolation {
+            Interpolation::Linear => p0 + (p1 - p0) * u,
+            Interpolation::CatmullRom => {
+                // tangents from the neighbouring keyframes, one sided at the ends
+                let tangent = |j: usize| {
+                    let (ta, pa) = keys[j.saturating_sub(1)];
+                    let (tb, pb) = keys[(j + 1).min(keys.len() - 1)];
+                    (pb - pa) / (tb - ta)
+                };
+                let m0 = tangent(i - 1);
+                let m1 = tangent(i);
+
+                // cubic Hermite basis
+                let u2 = u * u;
+                let u3 = u2 * u;
+                p0 * (2.0 * u3 - 3.0 * u2 + 1.0)
+                    + m0 * (dt * (u3 - 2.0 * u2 + u))
+                    + p1 * (-2.0 * u3 + 3.0 * u2)
+                    + m1 * (dt * (u3 - u2))
+            }
+        };
+        T::from_vec(&v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpolation, Keyframe, Keyframed};
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn keyframed_linear() {
+        let k = Keyframed::Keyframes(vec![
+            Keyframe {
+                time: 1.0,
+                value: 10.0,
+            },
+            Keyframe {
+                time: 0.0,
+                value: 0.0,
+            },
+        ]);
+        assert_is_close(k.at(-1.0, Interpolation::Linear), 0.0);
+        assert_is_close(k.at(0.25, Interpolation::Linear), 2.5);
+        assert_is_close(k.at(2.0, Interpolation::Linear), 10.0);
+        assert_is_close(Keyframed::Static(3.0).at(5.0, Interpolation::Linear), 3.0);
+    }
+
+    #[test]
+    fn keyframed_catmull_rom() {
+        let k = Keyframed::Keyframes(
+            [0.0, 1.0, 2.0, 3.0]
+                .iter()
+                .map(|t| Keyframe {
+                    time: *t,
+                    value: [*t, t * t, 1.0],
+                })
+                .collect(),
+        );
+        // passes through the keyframes
+        let v = k.at(1.0, Interpolation::CatmullRom);
+        assert_is_close(v[0], 1.0);
+        assert_is_close(v[1], 1.0);
+
+        // reproduces linear motion exactly, and is smooth for the parabola
+        let v = k.at(1.5, Interpolation::CatmullRom);
+        assert_is_close(v[0], 1.5);
+        assert_is_close(v[1], 2.25);
+        assert_is_close(v[2], 1.0);
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::objects::RayHit;
+use crate::ray::Ray;
+use crate::scene::Scene;
+
+/// Auxiliary output variables of the first hit, written alongside the
+/// rendered image for compositing and denoising.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Aov {
+    /// distance along the ray, 0 where nothing is hit
+    Depth,
+    /// world space normal, facing the ray
+    Normal,
+    Albedo,
+    /// world space position
+    Position,
+    /// 1 + index of the material among the materials of the scene
+    MaterialId,
+    /// 1 + index of the object in the world of the scene
+    ObjectId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    /// single channel passes
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Aov::Depth | Aov::MaterialId | Aov::ObjectId)
+    }
+
+    /// ids are taken from a single sample per pixel, as averaging them is
+    /// meaningless
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::MaterialId | Aov::ObjectId)
+    }
+
+    /// value of the pass for the camera ray `r`, hitting `hit`
+    pub fn value(&self, scene: &Scene, r: &Ray, hit: &RayHit) -> Color {
+        let rec = match hit {
+            RayHit::Hit(rec) => rec,
+            RayHit::NoHit => {
+                return match self {
+                    Aov::Albedo => scene.background.color(r),
+                    _ => Color::zero(),
+                }
+            }
+        };
+        let scalar = |v: f64| Color::new(v, v, v);
+        match self {
+            Aov::Depth => scalar(rec.distance),
+            Aov::Normal => rec.normal,
+            Aov::Albedo => rec.mat.albedo(rec),
+            Aov::Position => rec.p,
+            Aov::MaterialId => {
+                let i = scene
+                    .materials
+                    .iter()
+                    .position(|m| Arc::ptr_eq(m, &rec.mat));
+                scalar(i.map_or(0.0, |i| i as f64 + 1.0))
+            }
+            Aov::ObjectId => scalar(rec.object_id as f64),
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            "albedo" => Ok(Aov::Albedo),
+            "position" => Ok(Aov::Position),
+            "material_id" => Ok(Aov::MaterialId),
+            "object_id" => Ok(Aov::ObjectId),
+            _ => Err(format!(
+                "unknown pass '{}' (expected depth, normal, albedo, position, material_id or object_id)",
+                s
+            )),
+        }
+    }
+}
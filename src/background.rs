@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::{Color, KCD_PER_M2},
+    light::{DirectionalLight, LightPtr},
+    ray::Ray,
+    utils::{deg_to_rad, PI},
+    vec3::{dot, unit_vector, Vec3},
+};
+
+pub trait Background: Sync + Send {
+    fn color(&self, r: &Ray) -> Color;
+
+    /// light of the background sampled directly, such as the sun
+    fn light(&self) -> Option<LightPtr> {
+        None
+    }
+
+    /// color seen by rays scattered by non delta lobes, which leaves out
+    /// `light` as it is sampled directly at their origin
+    fn color_without_light(&self, r: &Ray) -> Color {
+        self.color(r)
+    }
+}
+
+pub type BackgroundPtr = Arc<dyn Background>;
+
+pub struct SkyGradient {}
+
+impl SkyGradient {
+    pub fn new() -> BackgroundPtr {
+        Arc::new(Self {})
+    }
+}
+
+impl Background for SkyGradient {
+    fn color(&self, r: &Ray) -> Color {
+        let unit_direction = unit_vector(&r.direction());
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+    }
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SolidColorDescription {
+    pub color: [f64; 3],
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> BackgroundPtr {
+        Arc::new(Self { color })
+    }
+
+    pub fn from(desc: &SolidColorDescription) -> BackgroundPtr {
+        Self::new(Color::new(desc.color[0], desc.color[1], desc.color[2]))
+    }
+}
+
+impl Background for SolidColor {
+    fn color(&self, _: &Ray) -> Color {
+        self.color
+    }
+}
+
+/// luminance of the sun outside of the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f64 = 2.0e6;
+
+/// angular diameter of the sun in degrees
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+/// Perez distribution of the sky relative to its zenith, for the angle
+/// `theta` from the zenith and `gamma` from the sun.
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta.max(1e-3)).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+/// Analytic daylight model of Preetham, Shirley and Smits, with a sun disk
+/// dimmed and reddened by the atmosphere and a uniform ground below the
+/// horizon.
+pub struct PreethamSky {
+    /// unit direction towards the sun
+    sun: Vec3,
+    theta_sun: f64,
+    /// zenith luminance and chromaticity
+    zenith: [f64; 3],
+    /// Perez coefficients of the luminance and chromaticity
+    coeffs: [[f64; 5]; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    ground: Color,
+    /// dimming of the sky through twilight
+    fade: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PreethamSkyDescription {
+    /// height of the sun above the horizon in degrees
+    pub sun_elevation: f64,
+    /// direction of the sun in degrees, from -z towards +x
+    #[serde(default)]
+    pub sun_azimuth: f64,
+    /// haziness of the atmosphere, from 2 for a clear sky to 10 for haze
+    #[serde(default = "default_turbidity")]
+    pub turbidity: f64,
+    #[serde(default = "default_ground_albedo")]
+    pub ground_albedo: [f64; 3],
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_ground_albedo() -> [f64; 3] {
+    [0.3, 0.3, 0.3]
+}
+
+impl PreethamSky {
+    pub fn new(
+        sun_elevation: f64,
+        sun_azimuth: f64,
+        turbidity: f64,
+        ground_albedo: Color,
+    ) -> BackgroundPtr {
+        Arc::new(Self::build(
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            ground_albedo,
+        ))
+    }
+
+    pub fn from(desc: &PreethamSkyDescription) -> BackgroundPtr {
+        let a = desc.ground_albedo;
+        Self::new(
+            desc.sun_elevation,
+            desc.sun_azimuth,
+            desc.turbidity,
+            Color::new(a[0], a[1], a[2]),
+        )
+    }
+
+    fn build(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, ground_albedo: Color) -> Self {
+        let t = turbidity.clamp(1.7, 10.0);
+        let elevation = deg_to_rad(sun_elevation.clamp(-90.0, 90.0));
+        let azimuth = deg_to_rad(sun_azimuth);
+        let sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        // the model holds for the sun above the horizon, the sky then
+        // fading out through twilight
+        let theta_sun = PI / 2.0 - elevation.max(0.0);
+        let fade = ((sun_elevation + 6.0) / 6.0).clamp(0.0, 1.0);
+
+        let (ts, ts2, ts3) = (theta_sun, theta_sun.powi(2), theta_sun.powi(3));
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t * t * (0.00166 * ts3 - 0.00375 * ts2 + 0.00209 * ts)
+            + t * (-0.02903 * ts3 + 0.06377 * ts2 - 0.03202 * ts + 0.00394)
+            + (0.11693 * ts3 - 0.21196 * ts2 + 0.06052 * ts + 0.25886);
+        let zenith_y = t * t * (0.00275 * ts3 - 0.00610 * ts2 + 0.00317 * ts)
+            + t * (-0.04214 * ts3 + 0.08970 * ts2 - 0.04153 * ts + 0.00516)
+            + (0.15346 * ts3 - 0.26756 * ts2 + 0.06670 * ts + 0.26688);
+        let coeffs = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // transmittance of the atmosphere along the sun rays, through
+        // Rayleigh and aerosol scattering at the wavelengths of the
+        // primaries in micrometers
+        let theta_sun_deg = 90.0 - sun_elevation.clamp(-90.0, 90.0);
+        let sun_radiance = if theta_sun_deg < 90.0 + SUN_ANGULAR_DIAMETER / 2.0 {
+            let m = 1.0
+                / (theta_sun_deg.min(90.0).to_radians().cos()
+                    + 0.15 * (93.885 - theta_sun_deg.min(90.0)).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = |lambda: f64| {
+                (-m * (0.008735 * lambda.powf(-4.08) + beta * lambda.powf(-1.3))).exp()
+            };
+            Color::new(
+                transmittance(0.65),
+                transmittance(0.57),
+                transmittance(0.475),
+            ) * (SUN_LUMINANCE * KCD_PER_M2)
+        } else {
+            Color::zero()
+        };
+
+        let mut sky = Self {
+            sun: unit_vector(&sun),
+            theta_sun,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            coeffs,
+            sun_radiance,
+            cos_sun_radius: deg_to_rad(SUN_ANGULAR_DIAMETER / 2.0).cos(),
+            ground: Color::zero(),
+            fade,
+        };
+
+        // the ground reflects the sun and the sky lighting it
+        let mut irradiance = sky.sun_radiance * (sky.sun_solid_angle() * sky.sun.y().max(0.0));
+        let n = 64;
+        for i in 0..n {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..2 * n {
+                let phi = (j as f64 + 0.5) / (2 * n) as f64 * 2.0 * PI;
+                let d = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                // uniform in the cosine, so weighted by it
+                irradiance += sky.sky(&d) * (cos_theta * 2.0 * PI / (2 * n * n) as f64);
+            }
+        }
+        sky.ground = ground_albedo * irradiance / PI;
+        sky
+    }
+
+    fn sun_solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.cos_sun_radius)
+    }
+
+    /// radiance of the sky in the unit direction `d`, above the horizon
+    fn sky(&self, d: &Vec3) -> Color {
+        let cos_theta = d.y();
+        let gamma = dot(d, &self.sun).clamp(-1.0, 1.0).acos();
+        let [lum, x, y]: [f64; 3] = std::array::from_fn(|i| {
+            self.zenith[i] * perez(&self.coeffs[i], cos_theta, gamma)
+                / perez(&self.coeffs[i], 1.0, self.theta_sun)
+        });
+        let lum = lum * KCD_PER_M2 * self.fade;
+        // xyY to linear sRGB
+        let big_x = x / y * lum;
+        let big_z = (1.0 - x - y) / y * lum;
+        Color::new(
+            (3.2406 * big_x - 1.5372 * lum - 0.4986 * big_z).max(0.0),
+            (-0.9689 * big_x + 1.8758 * lum + 0.0415 * big_z).max(0.0),
+            (0.0557 * big_x - 0.2040 * lum + 1.0570 * big_z).max(0.0),
+        )
+    }
+}
+
+impl Background for PreethamSky {
+    fn color(&self, r: &Ray) -> Color {
+        let d = unit_vector(&r.direction());
+        if dot(&d, &self.sun) >= self.cos_sun_radius {
+            self.color_without_light(r) + self.sun_radiance
+        } else {
+            self.color_without_light(r)
+        }
+    }
+
+    /// the sun as a directional light of its angular size
+    fn light(&self) -> Option<LightPtr> {
+        if self.sun_radiance.is_near_zero() {
+            return None;
+        }
+        Some(DirectionalLight::new(
+            -self.sun,
+            self.sun_radiance * self.sun_solid_angle(),
+            SUN_ANGULAR_DIAMETER,
+        ))
+    }
+
+    fn color_without_light(&self, r: &Ray) -> Color {
+        let d = unit_vector(&r.direction());
+        if d.y() < 0.0 {
+            self.ground
+        } else {
+            self.sky(&d)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(tag = "type")]
+pub enum BackgroundDescription {
+    #[default]
+    #[serde(rename = "sky_gradient")]
+    SkyGradient,
+    #[serde(rename = "solid")]
+    Solid(SolidColorDescription),
+    #[serde(rename = "sky")]
+    Sky(PreethamSkyDescription),
+}
+
+pub fn create_background(desc: &BackgroundDescription) -> BackgroundPtr {
+    match desc {
+        BackgroundDescription::SkyGradient => SkyGradient::new(),
+        BackgroundDescription::Solid(d) => SolidColor::from(d),
+        BackgroundDescription::Sky(d) => PreethamSky::from(d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PreethamSky;
+    use crate::color::Color;
+    use crate::ray::Ray;
+    use crate::utils::{deg_to_rad, PI};
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn sun_disk_is_left_to_the_light() {
+        let sky = PreethamSky::new(30.0, 0.0, 3.0, Color::new(0.3, 0.3, 0.3));
+        let e = deg_to_rad(30.0);
+        let r = Ray::new(Point3::zero(), Vec3::new(0.0, e.sin(), -e.cos()), 0.0);
+        let sun = sky.color(&r) - sky.color_without_light(&r);
+        let light = sky.light().unwrap().sample(&Point3::zero()).unwrap();
+        // the light carries the radiance of the disk over its solid angle
+        let solid_angle = 2.0 * PI * (1.0 - deg_to_rad(0.265).cos());
+        assert!(sun.y() > 1000.0);
+        assert!((light.irradiance.y() / (sun.y() * solid_angle) - 1.0).abs() < 0.0001);
+        assert!(light.wi.y() > 0.0);
+    }
+}
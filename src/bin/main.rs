@@ -1,26 +1,40 @@
+use std::io::{self, Write};
 use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand};
 
 extern crate rusty_rays;
-use rusty_rays::image::save_ppm;
-use rusty_rays::render::render;
-use rusty_rays::scene::parse_scene;
+use rusty_rays::aov::Aov;
+use rusty_rays::denoise::{denoise, DenoiseSettings, DENOISE_AOVS};
+use rusty_rays::film::Film;
+use rusty_rays::filter::FilterDescription;
+use rusty_rays::generate::{generate, GeneratorParams, Template};
+use rusty_rays::image::{load_ppm, save_pfm, save_ppm, Image, PixelCoord, PpmWriter};
+use rusty_rays::preview::{Preview, PreviewMode};
+use rusty_rays::render::{render_progressive, Region, RenderSettings};
+use rusty_rays::scene::{
+    build_scene, build_scene_at, load_scene_description, save_scene, Scene, SceneDescription,
+};
+use rusty_rays::watch::FileWatcher;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(disable_help_flag = true, subcommand_negates_reqs = true)]
 struct Args {
     /// output filename (without extension)
     #[arg(short, long, default_value_t = String::from("output"))]
     output: String,
 
     /// output width
-    #[arg(short, long, default_value_t = 640)]
-    width: u16,
+    #[arg(short, long, default_value_t = 640, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
 
     /// output height
-    #[arg(short, long, default_value_t = 360)]
-    height: u16,
+    #[arg(short, long, default_value_t = 360, value_parser = clap::value_parser!(u32).range(1..))]
+    height: u32,
 
     /// samples per pixel
     #[arg(short, long, default_value_t = 100)]
@@ -30,38 +44,388 @@ struct Args {
     #[arg(short, long, default_value_t = 50)]
     depth: u32,
 
+    /// trace sampled wavelengths rather than colors, for the dispersion of
+    /// glass
+    #[arg(long)]
+    spectral: bool,
+
     /// number of threads used for rendering
     #[arg(short, long, default_value_t = 1)]
     threads: u32,
 
+    /// number of progressive passes the samples are split into
+    /// [default: 1, or 10 with --preview]
+    #[arg(long)]
+    passes: Option<u32>,
+
+    /// show the render in the terminal, updated after every pass (auto, ansi or sixel)
+    #[arg(long, num_args = 0..=1, default_missing_value = "auto")]
+    preview: Option<PreviewMode>,
+
+    /// re-render whenever the scene file or its included files change,
+    /// refining the image one sample per pass
+    #[arg(long)]
+    watch: bool,
+
+    /// render the frames start..end (end excluded) of the animation as a
+    /// numbered image sequence
+    #[arg(long)]
+    frames: Option<FrameRange>,
+
+    /// frames per second of the animation
+    #[arg(long, default_value_t = 24.0)]
+    fps: f64,
+
+    /// render only the window x0,y0,x1,y1 of the frame (pixels from the top
+    /// left, the end excluded), saving just that crop
+    #[arg(long)]
+    region: Option<Region>,
+
+    /// paste the rendered region into the existing output image instead
+    #[arg(long, requires = "region")]
+    paste: bool,
+
+    /// pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos
+    /// [default: the film filter of the scene]
+    #[arg(long)]
+    filter: Option<FilterDescription>,
+
+    /// radius of the reconstruction filter in pixels
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    /// output passes of the first hit, written to `{output}_{pass}.pfm`:
+    /// depth, normal, albedo, position, material_id or object_id
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["paste", "strip_height"])]
+    aov: Vec<Aov>,
+
+    /// denoise the image, guided by its albedo, normal and depth passes
+    #[arg(long, conflicts_with = "strip_height")]
+    denoise: bool,
+
+    /// also save the noisy image, to `{output}_noisy.ppm`
+    #[arg(long, requires = "denoise")]
+    save_noisy: bool,
+
+    /// render in strips of this many rows, each written to the output as soon
+    /// as it is done, for images too large to hold in memory
+    #[arg(long, conflicts_with_all = ["paste", "watch"], value_parser = clap::value_parser!(u32).range(1..))]
+    strip_height: Option<u32>,
+
+    /// print help
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+
     /// json file with the scene
-    scene: String,
+    #[arg(required = true)]
+    scene: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone)]
+struct FrameRange {
+    start: u32,
+    end: u32,
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid frame range '{}' (expected start..end)", s);
+        let (start, end) = s.split_once("..").ok_or_else(err)?;
+        let range = Self {
+            start: start.parse().map_err(|_| err())?,
+            end: end.parse().map_err(|_| err())?,
+        };
+        if range.start >= range.end {
+            return Err(format!("empty frame range '{}' (the end is excluded)", s));
+        }
+        Ok(range)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// generate a scene from a built-in template and render it
+    Generate {
+        /// template: cover, cornell_box, material_grid or sphere_flake
+        template: Template,
+
+        /// grid size (cover, material_grid) or recursion levels (sphere_flake)
+        #[arg(long)]
+        size: Option<u32>,
+
+        /// seed for the random choices of the template
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// write the scene to this json file instead of rendering it
+        #[arg(long)]
+        json: Option<String>,
+    },
 }
 
 fn main() {
     // parse arguments
     let args = Args::parse();
 
+    let settings = RenderSettings {
+        width: args.width,
+        height: args.height,
+        samples_per_pixel: args.samples,
+        max_depth: args.depth,
+        threads: args.threads,
+        passes: args.passes.unwrap_or(if args.watch {
+            args.samples
+        } else if args.preview.is_some() {
+            10
+        } else {
+            1
+        }),
+        region: args.region,
+        aovs: if args.denoise {
+            let mut aovs = args.aov.clone();
+            aovs.extend(DENOISE_AOVS.iter().filter(|a| !args.aov.contains(a)));
+            aovs
+        } else {
+            args.aov.clone()
+        },
+        spectral: args.spectral,
+    };
+    if let Some(region) = &args.region {
+        if !region.fits(args.width, args.height) {
+            eprintln!(
+                "Region {},{},{},{} exceeds the {}x{} frame",
+                region.x0, region.y0, region.x1, region.y1, args.width, args.height
+            );
+            process::exit(1);
+        }
+    }
+
+    // scene description
+    let mut desc = match &args.command {
+        Some(Command::Generate {
+            template,
+            size,
+            seed,
+            json,
+        }) => {
+            let desc = generate(
+                *template,
+                &GeneratorParams {
+                    size: *size,
+                    seed: *seed,
+                },
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Unable to generate scene: {}", err);
+                process::exit(1)
+            });
+            if let Some(path) = json {
+                save_scene(path, &desc).unwrap_or_else(|err| {
+                    eprintln!("Error saving scene: {}", err);
+                    process::exit(1)
+                });
+                return;
+            }
+            desc
+        }
+        None => {
+            let path = args.scene.as_deref().unwrap_or_default();
+            if args.watch {
+                watch(&args, path, &settings);
+            }
+            load_scene_description(path, &mut vec![]).unwrap_or_else(|err| {
+                eprintln!("Unable to load scene from file '{}': {}", path, err);
+                process::exit(1)
+            })
+        }
+    };
+
+    override_film(&args, &mut desc);
+
+    match &args.frames {
+        Some(frames) => {
+            for frame in frames.start..frames.end {
+                println!("Frame {} of {}..{}", frame, frames.start, frames.end);
+                let time = frame as f64 / args.fps;
+                let output = format!("{}_{:04}", args.output, frame);
+                render_to_file(&args, &desc, time, &settings, &output);
+            }
+        }
+        None => render_to_file(&args, &desc, 0.0, &settings, &args.output),
+    }
+}
+
+/// Applies the film settings given on the command line.
+fn override_film(args: &Args, desc: &mut SceneDescription) {
+    if let Some(filter) = &args.filter {
+        desc.film.filter = filter.clone();
+    }
+    if let Some(radius) = args.filter_radius {
+        desc.film.filter.set_radius(radius);
+    }
+}
+
+fn render_to_file(
+    args: &Args,
+    desc: &SceneDescription,
+    time: f64,
+    settings: &RenderSettings,
+    output: &str,
+) {
     // world & camera
-    let scene = parse_scene(&args.scene).unwrap_or_else(|err| {
-        eprintln!("Unable to load scene from file '{}': {}", &args.scene, err);
+    let scene = build_scene_at(desc, time).unwrap_or_else(|err| {
+        eprintln!("Unable to build scene: {}", err);
         process::exit(1)
     });
 
-    // render
-    let image = render(
-        &scene.world,
-        &scene.camera,
-        args.width,
-        args.height,
-        args.samples,
-        args.depth,
-        args.threads,
-    );
-
-    // save to file
-    save_ppm(&args.output, &image).unwrap_or_else(|err| {
+    // render & save to file
+    let result = match args.strip_height {
+        Some(rows) => render_strips(args, &scene, settings, rows, output),
+        None => {
+            let film = render_film(args, &scene, settings);
+            save_film(args, output, &film)
+        }
+    };
+    result.unwrap_or_else(|err| {
         eprintln!("Error saving file: {}", err);
         process::exit(1)
     });
 }
+
+fn render_film(args: &Args, scene: &Scene, settings: &RenderSettings) -> Film {
+    let mut preview = args.preview.map(Preview::new);
+    render_progressive(scene, settings, |film| {
+        if let Some(p) = &mut preview {
+            p.show(film);
+        }
+        true
+    })
+}
+
+/// Saves the image of the film, denoised with `--denoise`, and the output
+/// passes that were asked for.
+fn save_film(args: &Args, output: &str, film: &Film) -> Result<(), String> {
+    if args.denoise {
+        if args.save_noisy {
+            save_image(args, &format!("{}_noisy", output), &film.to_image())?;
+        }
+        print!("Denoising... ");
+        io::stdout().flush().unwrap();
+        let colors = denoise(
+            film,
+            &DenoiseSettings {
+                threads: args.threads,
+                ..Default::default()
+            },
+        )?;
+        println!("done!");
+        let image = Image::from_colors(film.width(), film.height(), &colors);
+        save_image(args, output, &image)?;
+    } else {
+        save_image(args, output, &film.to_image())?;
+    }
+    save_aovs(args, output, film)
+}
+
+/// Saves each output pass of the film asked for to `{output}_{pass}.pfm`.
+fn save_aovs(args: &Args, output: &str, film: &Film) -> Result<(), String> {
+    for aov in film.aovs().into_iter().filter(|a| args.aov.contains(a)) {
+        let data = film.aov_data(aov).unwrap_or_default();
+        let name = format!("{}_{}", output, aov.name());
+        save_pfm(&name, film.width(), film.height(), &data, aov.is_scalar())?;
+    }
+    Ok(())
+}
+
+/// Renders the frame, or its region, from the top in strips of `rows` rows
+/// appended to the output file one after the other.
+fn render_strips(
+    args: &Args,
+    scene: &Scene,
+    settings: &RenderSettings,
+    rows: u32,
+    output: &str,
+) -> Result<(), String> {
+    let frame = settings
+        .region
+        .unwrap_or(Region::full(settings.width, settings.height));
+    let mut writer = PpmWriter::create(output, frame.width(), frame.height())?;
+    for y0 in (frame.y0..frame.y1).step_by(rows as usize) {
+        let y1 = (y0 + rows).min(frame.y1);
+        println!("Rows {}..{} of {}..{}", y0, y1, frame.y0, frame.y1);
+        let strip = RenderSettings {
+            region: Some(Region { y0, y1, ..frame }),
+            ..settings.clone()
+        };
+        writer.write_rows(&render_film(args, scene, &strip).to_image())?;
+    }
+    writer.finish()
+}
+
+/// Saves the render, or with `--paste` the existing output image with the
+/// rendered region pasted into it.
+fn save_image(args: &Args, output: &str, image: &Image) -> Result<(), String> {
+    let region = match &args.region {
+        Some(region) if args.paste => region,
+        _ => return save_ppm(output, image),
+    };
+    let mut full = load_ppm(&(output.to_owned() + ".ppm"))?;
+    if full.width() != args.width || full.height() != args.height {
+        return Err(format!(
+            "existing image is {}x{}, expected {}x{}",
+            full.width(),
+            full.height(),
+            args.width,
+            args.height
+        ));
+    }
+    // image rows go up from the bottom
+    let at = PixelCoord {
+        x: region.x0,
+        y: args.height - region.y1,
+    };
+    full.paste(image, &at);
+    save_ppm(output, &full)
+}
+
+fn watch(args: &Args, path: &str, settings: &RenderSettings) -> ! {
+    let mut files = vec![];
+    loop {
+        // snapshot the files before reading them, so that edits made while
+        // loading are not missed
+        let mut watcher = FileWatcher::new(&files);
+        let mut loaded = vec![];
+        let scene = load_scene_description(path, &mut loaded).and_then(|mut d| {
+            override_film(args, &mut d);
+            build_scene(&d)
+        });
+        watcher.add(&loaded);
+        files = watcher.files();
+
+        match scene {
+            Ok(scene) => {
+                let mut preview = args.preview.map(Preview::new);
+                let film = render_progressive(&scene, settings, |film| {
+                    if let Some(p) = &mut preview {
+                        p.show(film);
+                    }
+                    !watcher.changed()
+                });
+                if let Err(err) = save_film(args, &args.output, &film) {
+                    eprintln!("Error saving file: {}", err);
+                }
+            }
+            Err(err) => eprintln!("Unable to load scene from file '{}': {}", path, err),
+        }
+
+        println!("Watching for changes...");
+        while !watcher.changed() {
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+}
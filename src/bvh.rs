@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::objects::{Hittable, Object, RayHit};
+use crate::ray::Ray;
+
+/// Node of a bounding volume hierarchy, splitting its objects in halves
+/// along the longest axis of their bounding box.
+pub struct BvhNode {
+    left: Object,
+    right: Object,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    /// Builds the hierarchy for objects bounded between `time0` and `time1`,
+    /// returns `None` if there are no objects or one of them is unbounded.
+    pub fn new(objects: &[Object], time0: f64, time1: f64) -> Option<Object> {
+        let mut boxed = objects
+            .iter()
+            .map(|o| Some((o.clone(), o.bounding_box(time0, time1)?)))
+            .collect::<Option<Vec<_>>>()?;
+        if boxed.is_empty() {
+            return None;
+        }
+        Some(Self::build(&mut boxed))
+    }
+
+    fn build(objects: &mut [(Object, Aabb)]) -> Object {
+        if objects.len() == 1 {
+            return objects[0].0.clone();
+        }
+
+        let bbox = objects
+            .iter()
+            .map(|o| o.1)
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+        let centers = objects
+            .iter()
+            .map(|o| Aabb::new(o.1.center(), o.1.center()))
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+        let axis = centers.longest_axis();
+        objects.sort_by(|a, b| a.1.center()[axis].total_cmp(&b.1.center()[axis]));
+
+        let (l, r) = objects.split_at_mut(objects.len() / 2);
+        Arc::new(Self {
+            left: Self::build(l),
+            right: Self::build(r),
+            bbox,
+        })
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return RayHit::NoHit;
+        }
+        let hit_left = self.left.hit(r, t_min, t_max);
+        let closest_so_far = match hit_left {
+            RayHit::NoHit => t_max,
+            RayHit::Hit(ref rec) => rec.t,
+        };
+        match self.right.hit(r, t_min, closest_so_far) {
+            RayHit::NoHit => hit_left,
+            hit_right => hit_right,
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return 1.0;
+        }
+        let left = self.left.transmittance(r, t_min, t_max);
+        if left == 0.0 {
+            return 0.0;
+        }
+        left * self.right.transmittance(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _: f64, _: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
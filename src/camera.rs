@@ -1,83 +1,935 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
+use crate::animation::{Interpolation, Keyframed};
+use crate::color::Color;
+use crate::image::{load_ppm, Image};
+use crate::objects::{Hittable, RayHit, World};
 use crate::ray::Ray;
-use crate::utils::deg_to_rad;
-use crate::vec3::{cross, unit_vector, Point3, Vec3};
+use crate::utils::{deg_to_rad, random, random_between, INFINITY, PI};
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+pub trait Camera: Sync + Send {
+    /// Ray through the film at `(s, t)`, both in `[0, 1]` from the bottom left
+    /// corner, or `None` if the projection covers no direction there.
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray>;
 
-pub struct Camera {
+    /// Ray through the film at `(s, t)` with the weight of its contribution
+    /// to each color channel.
+    fn get_sample(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        self.get_ray(s, t).map(|r| (r, Color::new(1.0, 1.0, 1.0)))
+    }
+}
+
+pub type CameraPtr = Arc<dyn Camera>;
+
+/// Position, orientation and shutter interval shared by all projections.
+#[derive(Copy, Clone)]
+pub struct CameraFrame {
     origin: Point3,
-    lower_left_corner: Point3,
-    horizontal: Vec3,
-    vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    lens_radius: f64,
+    w: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct CameraDescription {
-    lookfrom: [f64; 3],
-    lookat: [f64; 3],
-    vup: [f64; 3],
-    vfov: f64,
-    aspect_ratio: f64,
-    aperture: f64,
-    focus_dist: f64,
-}
-
-impl Camera {
+impl CameraFrame {
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
         vup: Vec3,
+        shutter_open: f64,
+        shutter_close: f64,
+    ) -> Self {
+        let w = unit_vector(&(lookfrom - lookat));
+        let u = unit_vector(&cross(&vup, &w));
+        let v = cross(&w, &u);
+        Self {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            shutter_open,
+            shutter_close,
+        }
+    }
+
+    fn time(&self) -> f64 {
+        random_between(self.shutter_open, self.shutter_close)
+    }
+
+    /// world direction of a direction given in camera space, with the
+    /// camera looking down the negative z axis
+    fn world_direction(&self, d: &Vec3) -> Vec3 {
+        self.u * d.x() + self.v * d.y() + self.w * d.z()
+    }
+}
+
+/// Shape of the lens aperture, which sets the look of out of focus highlights.
+pub enum ApertureShape {
+    Disc,
+    /// regular polygon formed by the blades of the diaphragm
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    /// aperture drawn by the brightness of an image, kept as the cumulative
+    /// distribution of its pixels, top row first
+    Image {
+        cdf: Vec<f64>,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl ApertureShape {
+    pub fn from_image(img: &Image) -> Result<Self, String> {
+        let mut cdf = Vec::with_capacity(img.width() as usize * img.height() as usize);
+        let mut total = 0.0;
+        for p in img.iter() {
+            let c = img.get_color(&p);
+            total += 0.2126 * c.r as f64 + 0.7152 * c.g as f64 + 0.0722 * c.b as f64;
+            cdf.push(total);
+        }
+        if total == 0.0 {
+            return Err("aperture image is black".to_owned());
+        }
+        Ok(Self::Image {
+            cdf,
+            width: img.width() as usize,
+            height: img.height() as usize,
+        })
+    }
+
+    /// random point of the aperture, within the square `[-1, 1]^2`
+    fn sample(&self) -> Vec3 {
+        match self {
+            ApertureShape::Disc => Vec3::random_in_unit_disc(),
+            ApertureShape::Polygon { blades, rotation } => {
+                // uniform point in one of the triangles fanning from the center
+                let n = (*blades).max(3) as f64;
+                let k = (random() * n).floor();
+                let a0 = rotation + 2.0 * PI * k / n;
+                let a1 = a0 + 2.0 * PI / n;
+                let (mut r1, mut r2) = (random(), random());
+                if r1 + r2 > 1.0 {
+                    r1 = 1.0 - r1;
+                    r2 = 1.0 - r2;
+                }
+                Vec3::new(
+                    r1 * a0.cos() + r2 * a1.cos(),
+                    r1 * a0.sin() + r2 * a1.sin(),
+                    0.0,
+                )
+            }
+            ApertureShape::Image { cdf, width, height } => {
+                let target = random() * cdf[cdf.len() - 1];
+                let i = cdf.partition_point(|c| *c < target);
+                let i = i.min(cdf.len() - 1);
+                let scale = 2.0 / (*width).max(*height) as f64;
+                let x = (i % width) as f64 + random() - *width as f64 / 2.0;
+                let y = *height as f64 / 2.0 - (i / width) as f64 - random();
+                Vec3::new(x * scale, y * scale, 0.0)
+            }
+        }
+    }
+}
+
+/// Thin lens with the given aperture diameter and shape.
+#[derive(Clone)]
+pub struct Lens {
+    radius: f64,
+    shape: Arc<ApertureShape>,
+}
+
+impl Lens {
+    pub fn new(aperture: f64, shape: ApertureShape) -> Self {
+        Self {
+            radius: aperture / 2.0,
+            shape: Arc::new(shape),
+        }
+    }
+
+    fn sample_offset(&self, frame: &CameraFrame) -> Vec3 {
+        if self.radius == 0.0 {
+            return Vec3::zero();
+        }
+        let p = self.shape.sample() * self.radius;
+        frame.u * p.x() + frame.v * p.y()
+    }
+}
+
+/// Thin lens perspective camera.
+pub struct PerspectiveCamera {
+    frame: CameraFrame,
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    lens: Lens,
+}
+
+impl PerspectiveCamera {
+    pub fn new(
+        frame: CameraFrame,
         vfov: f64,
         aspect_ratio: f64,
-        aperture: f64,
+        lens: Lens,
         focus_dist: f64,
-    ) -> Self {
+    ) -> CameraPtr {
+        Self::new_stereo_eye(frame, vfov, aspect_ratio, lens, focus_dist, 0.0, 1.0)
+    }
+
+    /// Eye of a stereo pair, displaced by `eye_offset` along the horizontal
+    /// axis of `frame`. The frustum is sheared so that both eyes see the same
+    /// window at the `convergence` distance.
+    pub fn new_stereo_eye(
+        frame: CameraFrame,
+        vfov: f64,
+        aspect_ratio: f64,
+        lens: Lens,
+        focus_dist: f64,
+        eye_offset: f64,
+        convergence: f64,
+    ) -> CameraPtr {
         // viewport computation
         let theta = deg_to_rad(vfov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        // position and orientation
-        let w = unit_vector(&(lookfrom - lookat));
-        let u = unit_vector(&cross(&vup, &w));
-        let v = cross(&w, &u);
-        let origin = lookfrom;
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * viewport_height * focus_dist;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
-        Self {
-            origin,
+        let frame = CameraFrame {
+            origin: frame.origin + frame.u * eye_offset,
+            ..frame
+        };
+        let horizontal = frame.u * viewport_width * focus_dist;
+        let vertical = frame.v * viewport_height * focus_dist;
+        let shift = frame.u * (-eye_offset * focus_dist / convergence);
+        let lower_left_corner =
+            frame.origin - horizontal / 2.0 - vertical / 2.0 - frame.w * focus_dist + shift;
+        Arc::new(Self {
+            frame,
             lower_left_corner,
             horizontal,
             vertical,
-            u,
-            v,
-            lens_radius: aperture / 2.0,
+            lens,
+        })
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let f = &self.frame;
+        let offset = self.lens.sample_offset(f);
+        Some(Ray::new(
+            f.origin + offset,
+            self.lower_left_corner + self.horizontal * s + self.vertical * t - f.origin - offset,
+            f.time(),
+        ))
+    }
+}
+
+/// Parallel projection, with rays leaving a rectangle of the given width.
+pub struct OrthographicCamera {
+    frame: CameraFrame,
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    lens: Lens,
+    focus_dist: f64,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        frame: CameraFrame,
+        view_width: f64,
+        aspect_ratio: f64,
+        lens: Lens,
+        focus_dist: f64,
+    ) -> CameraPtr {
+        let horizontal = frame.u * view_width;
+        let vertical = frame.v * view_width / aspect_ratio;
+        let lower_left_corner = frame.origin - horizontal / 2.0 - vertical / 2.0;
+        Arc::new(Self {
+            frame,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            lens,
+            focus_dist,
+        })
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let f = &self.frame;
+        let offset = self.lens.sample_offset(f);
+        let origin = self.lower_left_corner + self.horizontal * s + self.vertical * t;
+        Some(Ray::new(
+            origin + offset,
+            -f.w * self.focus_dist - offset,
+            f.time(),
+        ))
+    }
+}
+
+/// Equidistant fisheye: the angle from the view direction grows linearly
+/// with the distance from the center of the film, reaching `fov / 2` at the
+/// top and bottom edges.
+pub struct FisheyeCamera {
+    frame: CameraFrame,
+    fov: f64,
+    aspect_ratio: f64,
+}
+
+impl FisheyeCamera {
+    pub fn new(frame: CameraFrame, fov: f64, aspect_ratio: f64) -> CameraPtr {
+        Arc::new(Self {
+            frame,
+            fov: deg_to_rad(fov),
+            aspect_ratio,
+        })
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let x = (s - 0.5) * self.aspect_ratio;
+        let y = t - 0.5;
+        let theta = 2.0 * (x * x + y * y).sqrt() * self.fov / 2.0;
+        if theta > PI {
+            return None;
         }
+        let phi = y.atan2(x);
+        let d = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        );
+        Some(Ray::new(
+            self.frame.origin,
+            self.frame.world_direction(&d),
+            self.frame.time(),
+        ))
     }
+}
+
+/// Full 360x180 degrees panorama, with longitude along the width and
+/// latitude along the height of the film.
+pub struct EquirectangularCamera {
+    frame: CameraFrame,
+    eye_offset: f64,
+    convergence: f64,
+}
 
-    pub fn from(desc: &CameraDescription) -> Self {
-        Self::new(
-            Point3::new(desc.lookfrom[0], desc.lookfrom[1], desc.lookfrom[2]),
-            Point3::new(desc.lookat[0], desc.lookat[1], desc.lookat[2]),
-            Point3::new(desc.vup[0], desc.vup[1], desc.vup[2]),
-            desc.vfov,
-            desc.aspect_ratio,
-            desc.aperture,
-            desc.focus_dist,
+impl EquirectangularCamera {
+    pub fn new(frame: CameraFrame) -> CameraPtr {
+        Self::new_stereo_eye(frame, 0.0, INFINITY)
+    }
+
+    /// Eye of an omni-directional stereo panorama: every ray leaves from a
+    /// circle of radius `eye_offset`, tangent to the viewing direction, and
+    /// aims at the point at `convergence` distance along it. The offset fades
+    /// out towards the poles, where the eyes would otherwise swap.
+    pub fn new_stereo_eye(frame: CameraFrame, eye_offset: f64, convergence: f64) -> CameraPtr {
+        Arc::new(Self {
+            frame,
+            eye_offset,
+            convergence,
+        })
+    }
+}
+
+/// Camera space direction for equirectangular film coordinates, looking
+/// forward at the center of the film.
+fn equirectangular_direction(s: f64, t: f64) -> Vec3 {
+    let phi = (s - 0.5) * 2.0 * PI;
+    let theta = (t - 0.5) * PI;
+    Vec3::new(
+        theta.cos() * phi.sin(),
+        theta.sin(),
+        -theta.cos() * phi.cos(),
+    )
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let d = equirectangular_direction(s, t);
+        if self.eye_offset == 0.0 {
+            return Some(Ray::new(
+                self.frame.origin,
+                self.frame.world_direction(&d),
+                self.frame.time(),
+            ));
+        }
+
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (t - 0.5) * PI;
+        let right = Vec3::new(phi.cos(), 0.0, phi.sin());
+        let offset = right * (self.eye_offset * theta.cos());
+        let dir = if self.convergence.is_finite() {
+            d * self.convergence - offset
+        } else {
+            d
+        };
+        Some(Ray::new(
+            self.frame.origin + self.frame.world_direction(&offset),
+            self.frame.world_direction(&dir),
+            self.frame.time(),
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum StereoLayout {
+    /// left eye on the left half of the image
+    #[default]
+    #[serde(rename = "side_by_side")]
+    SideBySide,
+    /// left eye on the top half of the image
+    #[serde(rename = "top_bottom")]
+    TopBottom,
+}
+
+/// Left and right eye images packed in a single film.
+pub struct StereoCamera {
+    left: CameraPtr,
+    right: CameraPtr,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(left: CameraPtr, right: CameraPtr, layout: StereoLayout) -> CameraPtr {
+        Arc::new(Self {
+            left,
+            right,
+            layout,
+        })
+    }
+
+    /// eye seeing the film at `(s, t)`, with the coordinates on its own film
+    fn eye(&self, s: f64, t: f64) -> (&CameraPtr, f64, f64) {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (&self.left, 2.0 * s, t),
+            StereoLayout::SideBySide => (&self.right, 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (&self.left, s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (&self.right, s, 2.0 * t),
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_ray(s, t)
+    }
+
+    fn get_sample(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_sample(s, t)
+    }
+}
+
+/// Brown-Conrady lens distortion, with radial coefficients `k1`, `k2`, `k3`
+/// and tangential coefficients `p1`, `p2`.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Distortion {
+    #[serde(default)]
+    pub k1: f64,
+    #[serde(default)]
+    pub k2: f64,
+    #[serde(default)]
+    pub k3: f64,
+    #[serde(default)]
+    pub p1: f64,
+    #[serde(default)]
+    pub p2: f64,
+    /// the coefficients map image coordinates to undistorted ones instead,
+    /// for undistorting plates
+    #[serde(default)]
+    pub inverse: bool,
+}
+
+impl Distortion {
+    /// distorted position of the undistorted point `(x, y)`
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
         )
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = Vec3::random_in_unit_disc() * self.lens_radius;
-        let offset = self.u * rd.x() + self.v * rd.y();
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+    /// undistorted position of the distorted point `(x, y)`, found by
+    /// fixed point iteration
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut ux, mut uy) = (x, y);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            ux += x - dx;
+            uy += y - dy;
+        }
+        (ux, uy)
+    }
+}
+
+/// Wraps a camera with the imperfections of a real lens: distortion, natural
+/// vignetting and lateral chromatic aberration.
+pub struct LensEffectsCamera {
+    camera: CameraPtr,
+    distortion: Distortion,
+    /// film coordinates are mapped to `[-aspect * scale, aspect * scale] x
+    /// [-scale, scale]` for the distortion model
+    aspect_ratio: f64,
+    scale: f64,
+    /// optical axis, for vignetting
+    axis: Vec3,
+    vignetting: bool,
+    /// relative magnification of the red channel, the opposite for blue
+    chromatic_aberration: f64,
+}
+
+impl LensEffectsCamera {
+    pub fn new(
+        camera: CameraPtr,
+        frame: &CameraFrame,
+        aspect_ratio: f64,
+        scale: f64,
+        desc: &LensEffectsDescription,
+    ) -> CameraPtr {
+        Arc::new(Self {
+            camera,
+            distortion: desc.distortion.unwrap_or_default(),
+            aspect_ratio,
+            scale,
+            axis: -frame.w,
+            vignetting: desc.vignetting,
+            chromatic_aberration: desc.chromatic_aberration,
+        })
+    }
+
+    /// film coordinates seen through the lens at `(s, t)`, magnified by
+    /// `magnification`
+    fn remap(&self, s: f64, t: f64, magnification: f64) -> (f64, f64) {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio * self.scale * magnification;
+        let y = (2.0 * t - 1.0) * self.scale * magnification;
+        let (x, y) = if self.distortion.inverse {
+            self.distortion.distort(x, y)
+        } else {
+            self.distortion.undistort(x, y)
+        };
+        (
+            (x / (self.aspect_ratio * self.scale) + 1.0) / 2.0,
+            (y / self.scale + 1.0) / 2.0,
         )
     }
 }
+
+impl Camera for LensEffectsCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (s, t) = self.remap(s, t, 1.0);
+        self.camera.get_ray(s, t)
+    }
+
+    fn get_sample(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        let (magnification, mut weight) = if self.chromatic_aberration == 0.0 {
+            (1.0, Color::new(1.0, 1.0, 1.0))
+        } else {
+            // one channel per sample, each imaged with its own magnification
+            match (random() * 3.0) as u32 {
+                0 => (1.0 + self.chromatic_aberration, Color::new(3.0, 0.0, 0.0)),
+                1 => (1.0, Color::new(0.0, 3.0, 0.0)),
+                _ => (1.0 - self.chromatic_aberration, Color::new(0.0, 0.0, 3.0)),
+            }
+        };
+        let (s, t) = self.remap(s, t, magnification);
+        let (r, w) = self.camera.get_sample(s, t)?;
+        if self.vignetting {
+            let cos_theta = dot(&unit_vector(&r.direction()), &self.axis).max(0.0);
+            weight *= cos_theta.powi(4);
+        }
+        Some((r, w * weight))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Projection {
+    #[default]
+    #[serde(rename = "perspective")]
+    Perspective,
+    #[serde(rename = "orthographic")]
+    Orthographic,
+    #[serde(rename = "fisheye")]
+    Fisheye,
+    #[serde(rename = "equirectangular")]
+    Equirectangular,
+}
+
+/// Distance to the plane in focus.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum FocusDistance {
+    /// focuses on the `lookat` point
+    Auto(AutoFocus),
+    Fixed(Keyframed<f64>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum AutoFocus {
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl Default for FocusDistance {
+    fn default() -> Self {
+        Self::Auto(AutoFocus::Auto)
+    }
+}
+
+/// What the camera focuses on, found by a probe ray cast before rendering.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum FocusTarget {
+    /// name of an object of the world
+    Object(String),
+    /// screen coordinate `[s, t]`, both in `[0, 1]` from the bottom left
+    Screen([f64; 2]),
+}
+
+fn default_vfov() -> Keyframed<f64> {
+    Keyframed::Static(90.0)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CameraDescription {
+    #[serde(rename = "type", default)]
+    pub projection: Projection,
+    pub lookfrom: Keyframed<[f64; 3]>,
+    pub lookat: Keyframed<[f64; 3]>,
+    pub vup: [f64; 3],
+    /// vertical field of view in degrees, for perspective and fisheye cameras
+    #[serde(default = "default_vfov")]
+    pub vfov: Keyframed<f64>,
+    /// width of the view of orthographic cameras
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_width: Option<Keyframed<f64>>,
+    pub aspect_ratio: f64,
+    /// diameter of the lens
+    #[serde(default)]
+    pub aperture: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aperture_shape: Option<ApertureShapeDescription>,
+    #[serde(default)]
+    pub focus_dist: FocusDistance,
+    /// overrides `focus_dist` with the distance to an object or a point on screen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_on: Option<FocusTarget>,
+    /// derives field of view, aperture, shutter interval and exposure from
+    /// the settings of a real camera
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical: Option<PhysicalCameraDescription>,
+    /// distortion, vignetting and chromatic aberration of a real lens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens_effects: Option<LensEffectsDescription>,
+    /// interpolation between keyframes
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// circles `lookfrom` around the `lookat` point over time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<OrbitDescription>,
+    /// renders both eyes of a stereo pair, with `aspect_ratio` that of each eye
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stereo: Option<StereoDescription>,
+    #[serde(default)]
+    pub shutter_open: f64,
+    #[serde(default)]
+    pub shutter_close: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct LensEffectsDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distortion: Option<Distortion>,
+    /// natural cos^4 falloff of the light towards the edges
+    #[serde(default)]
+    pub vignetting: bool,
+    /// relative magnification of the red channel, the opposite for blue
+    #[serde(default)]
+    pub chromatic_aberration: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ApertureShapeDescription {
+    #[serde(rename = "disc")]
+    Disc,
+    #[serde(rename = "polygon")]
+    Polygon {
+        blades: u32,
+        /// rotation of the blades in degrees
+        #[serde(default)]
+        rotation: f64,
+    },
+    /// ppm image, white where the aperture is open
+    #[serde(rename = "image")]
+    Image { file: String },
+}
+
+fn default_sensor_width() -> f64 {
+    36.0
+}
+
+fn default_iso() -> f64 {
+    100.0
+}
+
+fn default_unit() -> f64 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PhysicalCameraDescription {
+    /// focal length in millimeters
+    pub focal_length: f64,
+    /// sensor width in millimeters, full frame by default; the height
+    /// follows from the aspect ratio
+    #[serde(default = "default_sensor_width")]
+    pub sensor_width: f64,
+    pub f_number: f64,
+    /// exposure time in seconds, which is also the shutter interval
+    pub shutter_speed: f64,
+    #[serde(default = "default_iso")]
+    pub iso: f64,
+    /// length of a scene unit in meters
+    #[serde(default = "default_unit")]
+    pub unit: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StereoDescription {
+    /// distance between the eyes
+    pub interocular: f64,
+    /// distance at which the views of the eyes converge
+    pub convergence: f64,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrbitDescription {
+    /// duration of a full turn around the `vup` axis
+    pub period: f64,
+}
+
+impl CameraDescription {
+    /// shutter interval, relative to the time of the frame
+    pub fn shutter(&self) -> (f64, f64) {
+        match &self.physical {
+            Some(p) => (self.shutter_open, self.shutter_open + p.shutter_speed),
+            None => (self.shutter_open, self.shutter_close),
+        }
+    }
+
+    /// Scale of the radiance reaching the film. It is one for cameras that are
+    /// not physical and, for physical ones, at the "sunny 16" settings
+    /// (f/16 with a shutter speed of one over the ISO).
+    pub fn exposure(&self) -> f64 {
+        match &self.physical {
+            Some(p) => 256.0 * p.shutter_speed * p.iso / (p.f_number * p.f_number),
+            None => 1.0,
+        }
+    }
+
+    fn lens(&self) -> Result<Lens, String> {
+        let aperture = match &self.physical {
+            Some(p) => p.focal_length / p.f_number / 1000.0 / p.unit,
+            None => self.aperture,
+        };
+        let shape = match &self.aperture_shape {
+            None | Some(ApertureShapeDescription::Disc) => ApertureShape::Disc,
+            Some(ApertureShapeDescription::Polygon { blades, rotation }) => {
+                ApertureShape::Polygon {
+                    blades: *blades,
+                    rotation: deg_to_rad(*rotation),
+                }
+            }
+            Some(ApertureShapeDescription::Image { file }) => {
+                ApertureShape::from_image(&load_ppm(file)?)?
+            }
+        };
+        Ok(Lens::new(aperture, shape))
+    }
+}
+
+/// Rotates `v` around the unit vector `axis` (Rodrigues' formula).
+fn rotate(v: &Vec3, axis: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    *v * cos + cross(axis, v) * sin + *axis * dot(axis, v) * (1.0 - cos)
+}
+
+/// Camera at the given time of its animation, with the shutter opening
+/// `shutter_open` after it.
+pub fn create_camera(
+    desc: &CameraDescription,
+    time: f64,
+    world: &World,
+) -> Result<CameraPtr, String> {
+    let interp = desc.interpolation;
+    let lookfrom = desc.lookfrom.at(time, interp);
+    let lookfrom = Point3::new(lookfrom[0], lookfrom[1], lookfrom[2]);
+    let lookat = desc.lookat.at(time, interp);
+    let lookat = Point3::new(lookat[0], lookat[1], lookat[2]);
+    let vup = Vec3::new(desc.vup[0], desc.vup[1], desc.vup[2]);
+
+    let lookfrom = match &desc.orbit {
+        Some(orbit) => {
+            let angle = 2.0 * PI * time / orbit.period;
+            lookat + rotate(&(lookfrom - lookat), &unit_vector(&vup), angle)
+        }
+        None => lookfrom,
+    };
+
+    let (shutter_open, shutter_close) = desc.shutter();
+    let frame = CameraFrame::new(
+        lookfrom,
+        lookat,
+        vup,
+        time + shutter_open,
+        time + shutter_close,
+    );
+    let vfov = match &desc.physical {
+        Some(p) => {
+            let sensor_height = p.sensor_width / desc.aspect_ratio;
+            2.0 * (sensor_height / (2.0 * p.focal_length)).atan() * 180.0 / PI
+        }
+        None => desc.vfov.at(time, interp),
+    };
+
+    let eye = |lens: &Lens,
+               focus_dist: f64,
+               eye_offset: f64,
+               convergence: f64|
+     -> Result<CameraPtr, String> {
+        let camera = match desc.projection {
+            Projection::Perspective => PerspectiveCamera::new_stereo_eye(
+                frame,
+                vfov,
+                desc.aspect_ratio,
+                lens.clone(),
+                focus_dist,
+                eye_offset,
+                convergence,
+            ),
+            Projection::Equirectangular => {
+                EquirectangularCamera::new_stereo_eye(frame, eye_offset, convergence)
+            }
+            _ if eye_offset != 0.0 => {
+                return Err("stereo requires a perspective or equirectangular camera".to_owned())
+            }
+            Projection::Orthographic => {
+                let view_width = match &desc.view_width {
+                    Some(w) => w.at(time, interp),
+                    None => return Err("orthographic camera requires 'view_width'".to_owned()),
+                };
+                OrthographicCamera::new(
+                    frame,
+                    view_width,
+                    desc.aspect_ratio,
+                    lens.clone(),
+                    focus_dist,
+                )
+            }
+            Projection::Fisheye => FisheyeCamera::new(frame, vfov, desc.aspect_ratio),
+        };
+        Ok(match &desc.lens_effects {
+            Some(effects) => {
+                // perspective film coordinates in focal length units, as
+                // with usual lens calibrations
+                let scale = match desc.projection {
+                    Projection::Perspective => (deg_to_rad(vfov) / 2.0).tan(),
+                    _ => 1.0,
+                };
+                LensEffectsCamera::new(camera, &frame, desc.aspect_ratio, scale, effects)
+            }
+            None => camera,
+        })
+    };
+
+    // depth along the view direction of what a probe ray hits, the lookat
+    // point if it hits nothing
+    let probe = |r: Ray| match world.hit(&r, 0.001, INFINITY) {
+        RayHit::Hit(rec) => dot(&(rec.p - lookfrom), &-frame.w),
+        RayHit::NoHit => (lookat - lookfrom).length(),
+    };
+    let focus_dist = match (&desc.focus_on, &desc.focus_dist) {
+        (Some(FocusTarget::Object(name)), _) => {
+            let object = match world.get(name) {
+                Some(o) => o,
+                None => return Err(format!("focus object '{}' not defined", name)),
+            };
+            let target = match object.bounding_box(frame.shutter_open, frame.shutter_close) {
+                Some(b) => b.center(),
+                None => return Err(format!("focus object '{}' is unbounded", name)),
+            };
+            probe(Ray::new(lookfrom, target - lookfrom, frame.time()))
+        }
+        (Some(FocusTarget::Screen([s, t])), _) => {
+            let pinhole = Lens::new(0.0, ApertureShape::Disc);
+            match eye(&pinhole, 1.0, 0.0, INFINITY)?.get_ray(*s, *t) {
+                Some(r) => probe(r),
+                None => return Err("focus point is outside of the projection".to_owned()),
+            }
+        }
+        (None, FocusDistance::Auto(_)) => (lookat - lookfrom).length(),
+        (None, FocusDistance::Fixed(d)) => d.at(time, interp),
+    };
+
+    let lens = desc.lens()?;
+    match &desc.stereo {
+        Some(stereo) => Ok(StereoCamera::new(
+            eye(
+                &lens,
+                focus_dist,
+                -stereo.interocular / 2.0,
+                stereo.convergence,
+            )?,
+            eye(
+                &lens,
+                focus_dist,
+                stereo.interocular / 2.0,
+                stereo.convergence,
+            )?,
+            stereo.layout,
+        )),
+        None => eye(&lens, focus_dist, 0.0, INFINITY),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Distortion;
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn distortion_roundtrip() {
+        let d = Distortion {
+            k1: -0.2,
+            k2: 0.05,
+            k3: 0.0,
+            p1: 0.01,
+            p2: -0.005,
+            inverse: false,
+        };
+        let (x, y) = d.distort(0.4, -0.3);
+        assert!(x.hypot(y) < 0.5);
+        let (x, y) = d.undistort(x, y);
+        assert_is_close(x, 0.4);
+        assert_is_close(y, -0.3);
+    }
+}
@@ -3,6 +3,10 @@ use crate::vec3::Vec3;
 
 pub type Color = Vec3;
 
+/// luminance of 1 kcd/m² in the radiance units of the renderer, which put a
+/// white ground lit by the sun at noon around 1
+pub const KCD_PER_M2: f64 = 1.0 / 40.0;
+
 pub fn color_to_pixel(c: &Color, samples_per_pixel: u32) -> PixelColor {
     let scale = 1.0 / samples_per_pixel as f64;
 
@@ -12,3 +16,8 @@ pub fn color_to_pixel(c: &Color, samples_per_pixel: u32) -> PixelColor {
         b: (256.0 * (c.z() * scale).sqrt().clamp(0.0, 0.999)) as u8,
     }
 }
+
+/// luminance of a linear sRGB color
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
@@ -0,0 +1,178 @@
+use std::thread;
+
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::film::Film;
+use crate::vec3::dot;
+
+/// Passes of the film needed to guide the denoiser.
+pub const DENOISE_AOVS: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+/// Standard deviations of the weights of the denoiser.
+pub struct DenoiseSettings {
+    /// half size of the window in pixels
+    pub radius: u32,
+    pub sigma_spatial: f64,
+    pub sigma_albedo: f64,
+    /// on one minus the cosine between normals
+    pub sigma_normal: f64,
+    /// on the depth difference relative to the depth
+    pub sigma_depth: f64,
+    pub threads: u32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            radius: 6,
+            sigma_spatial: 4.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+            threads: 1,
+        }
+    }
+}
+
+/// Joint cross-bilateral filter of the colors of the film, guided by its
+/// albedo, normal and depth passes so as to keep the edges they show. The
+/// lighting is filtered apart from the albedo, which keeps the texture.
+/// Returns the colors with rows going up from the bottom.
+pub fn denoise(film: &Film, settings: &DenoiseSettings) -> Result<Vec<Color>, String> {
+    let pass = |aov: Aov| {
+        film.aov_data(aov)
+            .ok_or_else(|| format!("denoising requires the {} pass", aov.name()))
+    };
+    let albedo = pass(Aov::Albedo)?;
+    let normal = pass(Aov::Normal)?;
+    let depth = pass(Aov::Depth)?;
+    let color = film.colors();
+
+    // lighting, with the albedo divided out
+    let eps = 1e-3;
+    let light: Vec<Color> = color
+        .iter()
+        .zip(&albedo)
+        .map(|(c, a)| {
+            Color::new(
+                c.x() / a.x().max(eps),
+                c.y() / a.y().max(eps),
+                c.z() / a.z().max(eps),
+            )
+        })
+        .collect();
+
+    let width = film.width() as i64;
+    let height = film.height() as i64;
+    let r = settings.radius as i64;
+    let inv = |sigma: f64| 1.0 / (2.0 * sigma * sigma);
+    let (ks, ka, kn, kd) = (
+        inv(settings.sigma_spatial),
+        inv(settings.sigma_albedo),
+        inv(settings.sigma_normal),
+        inv(settings.sigma_depth),
+    );
+
+    let filter_row = |y: i64| -> Vec<Color> {
+        let mut row = Vec::with_capacity(width as usize);
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            let mut sum = Color::zero();
+            let mut total = 0.0;
+            for qy in (y - r).max(0)..(y + r + 1).min(height) {
+                for qx in (x - r).max(0)..(x + r + 1).min(width) {
+                    let j = (qy * width + qx) as usize;
+                    let d2 = ((qx - x) * (qx - x) + (qy - y) * (qy - y)) as f64;
+                    let dn = 1.0 - dot(&normal[i], &normal[j]).clamp(-1.0, 1.0);
+                    let dd = (depth[i].x() - depth[j].x()) / depth[i].x().max(eps);
+                    let w = (-(d2 * ks
+                        + (albedo[i] - albedo[j]).length_squared() * ka
+                        + dn * dn * kn
+                        + dd * dd * kd))
+                        .exp();
+                    sum += light[j] * w;
+                    total += w;
+                }
+            }
+            let l = sum / total;
+            let a = albedo[i];
+            row.push(Color::new(
+                l.x() * a.x().max(eps),
+                l.y() * a.y().max(eps),
+                l.z() * a.z().max(eps),
+            ));
+        }
+        row
+    };
+
+    // threads take the rows in turn
+    let threads = settings.threads.max(1) as i64;
+    let mut rows: Vec<Vec<Color>> = vec![vec![]; height as usize];
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let filter_row = &filter_row;
+                s.spawn(move || {
+                    (t..height)
+                        .step_by(threads as usize)
+                        .map(|y| (y, filter_row(y)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for h in handles {
+            for (y, row) in h.join().unwrap() {
+                rows[y as usize] = row;
+            }
+        }
+    });
+    let out: Vec<Color> = rows.into_iter().flatten().collect();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{denoise, DenoiseSettings, DENOISE_AOVS};
+    use crate::color::Color;
+    use crate::film::Film;
+    use crate::filter::BoxFilter;
+    use crate::image::PixelCoord;
+
+    #[test]
+    fn denoise_keeps_albedo_edges() {
+        // two albedos split down the middle, the right one lit twice as
+        // much, under noise alternating from pixel to pixel
+        let (width, height) = (16, 8);
+        let mut film = Film::new(width, height, &BoxFilter::new(0.5), &DENOISE_AOVS);
+        let region = |x: u32| {
+            if x < width / 2 {
+                (0.2, 1.0)
+            } else {
+                (0.8, 2.0)
+            }
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let (albedo, light) = region(x);
+                let noise = if (x + y) % 2 == 0 { 1.5 } else { 0.5 };
+                let c = albedo * light * noise;
+                film.add_sample(x as f64 + 0.5, y as f64 + 0.5, &Color::new(c, c, c));
+                let p = PixelCoord { x, y };
+                let aovs = [
+                    Color::new(albedo, albedo, albedo),
+                    Color::new(0.0, 1.0, 0.0),
+                    Color::new(1.0, 1.0, 1.0),
+                ];
+                film.add_aov_samples(&p, &aovs, 1);
+            }
+        }
+        film.add_samples_per_pixel(1);
+
+        let out = denoise(&film, &DenoiseSettings::default()).unwrap();
+        for (i, c) in out.iter().enumerate() {
+            let (albedo, light) = region(i as u32 % width);
+            let expected = albedo * light;
+            assert!((c.x() - expected).abs() < 0.05 * expected);
+        }
+    }
+}
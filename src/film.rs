@@ -0,0 +1,137 @@
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::filter::FilterPtr;
+use crate::image::{Image, PixelCoord};
+
+/// HDR accumulation buffer, holding the filter weighted sum of the samples
+/// around each pixel
+pub struct Film {
+    data: Vec<Color>,
+    weights: Vec<f64>,
+    width: u32,
+    height: u32,
+    samples: u32,
+    filter: FilterPtr,
+    /// output passes, with the sum of their values and the number of
+    /// samples of each pixel
+    aovs: Vec<(Aov, Vec<Color>)>,
+    aov_samples: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: &FilterPtr, aovs: &[Aov]) -> Self {
+        let size = width as usize * height as usize;
+        Self {
+            data: vec![Color::zero(); size],
+            weights: vec![0.0; size],
+            width,
+            height,
+            samples: 0,
+            filter: filter.clone(),
+            aovs: aovs
+                .iter()
+                .map(|a| (*a, vec![Color::zero(); size]))
+                .collect(),
+            aov_samples: vec![0; if aovs.is_empty() { 0 } else { size }],
+        }
+    }
+
+    /// Splats a sample taken at `(x, y)`, in pixels from the bottom left
+    /// corner, into the pixels within the filter radius.
+    pub fn add_sample(&mut self, x: f64, y: f64, c: &Color) {
+        let r = self.filter.radius();
+        // pixel centers are at half integers
+        let x0 = (x - 0.5 - r).ceil().max(0.0) as u32;
+        let x1 = ((x - 0.5 + r).floor() + 1.0).clamp(0.0, self.width as f64) as u32;
+        let y0 = (y - 0.5 - r).ceil().max(0.0) as u32;
+        let y1 = ((y - 0.5 + r).floor() + 1.0).clamp(0.0, self.height as f64) as u32;
+        for j in y0..y1 {
+            for i in x0..x1 {
+                let w = self
+                    .filter
+                    .evaluate(x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                if w != 0.0 {
+                    let k = j as usize * self.width as usize + i as usize;
+                    self.data[k] += *c * w;
+                    self.weights[k] += w;
+                }
+            }
+        }
+    }
+
+    pub fn aovs(&self) -> Vec<Aov> {
+        self.aovs.iter().map(|a| a.0).collect()
+    }
+
+    /// Adds `n` samples of the output passes to a pixel, with `values` the
+    /// sum of their values in the order of the passes, or the value of one of
+    /// them for ids.
+    pub fn add_aov_samples(&mut self, p: &PixelCoord, values: &[Color], n: u32) {
+        let i = p.y as usize * self.width as usize + p.x as usize;
+        for ((aov, data), v) in self.aovs.iter_mut().zip(values) {
+            if !aov.is_id() {
+                data[i] += *v;
+            } else if self.aov_samples[i] == 0 {
+                data[i] = *v;
+            }
+        }
+        self.aov_samples[i] += n;
+    }
+
+    /// values of an output pass, averaged over the samples of each pixel,
+    /// with rows going up from the bottom
+    pub fn aov_data(&self, aov: Aov) -> Option<Vec<Color>> {
+        let (aov, data) = self.aovs.iter().find(|a| a.0 == aov)?;
+        Some(
+            data.iter()
+                .zip(&self.aov_samples)
+                .map(|(v, n)| {
+                    if aov.is_id() {
+                        *v
+                    } else {
+                        *v / (*n).max(1) as f64
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// filtered color of a pixel over the samples accumulated so far
+    pub fn get_color(&self, p: &PixelCoord) -> Color {
+        let i = p.y as usize * self.width as usize + p.x as usize;
+        if self.weights[i] > 0.0 {
+            self.data[i] / self.weights[i]
+        } else {
+            Color::zero()
+        }
+    }
+
+    pub fn add_samples_per_pixel(&mut self, n: u32) {
+        self.samples += n;
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples
+    }
+
+    /// filtered colors of all pixels, with rows going up from the bottom
+    pub fn colors(&self) -> Vec<Color> {
+        self.data
+            .iter()
+            .zip(&self.weights)
+            .map(|(c, w)| if *w > 0.0 { *c / *w } else { Color::zero() })
+            .collect()
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image::from_colors(self.width, self.height, &self.colors())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
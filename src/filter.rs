@@ -0,0 +1,317 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::PI;
+
+/// Pixel reconstruction filter, weighting samples by their offset from the
+/// pixel center.
+pub trait Filter: Sync + Send {
+    /// extent of the filter in pixels, in each direction
+    fn radius(&self) -> f64;
+    /// weight of a sample at offset `(x, y)` from the pixel center
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+pub type FilterPtr = Arc<dyn Filter>;
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> FilterPtr {
+        Arc::new(Self { radius })
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        // half open, so that each sample lands in a single pixel with radius 0.5
+        let inside = |v: f64| (-self.radius..self.radius).contains(&v);
+        if inside(x) && inside(y) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> FilterPtr {
+        Arc::new(Self { radius })
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+/// Gaussian shifted down to reach zero at the radius.
+pub struct GaussianFilter {
+    radius: f64,
+    alpha: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, alpha: f64) -> FilterPtr {
+        Arc::new(Self { radius, alpha })
+    }
+
+    fn gaussian(&self, v: f64) -> f64 {
+        ((-self.alpha * v * v).exp() - (-self.alpha * self.radius * self.radius).exp()).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+/// Mitchell-Netravali cubic, stretched over the radius.
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> FilterPtr {
+        Arc::new(Self { radius, b, c })
+    }
+
+    fn mitchell(&self, v: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * v / self.radius).abs();
+        if x < 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        } else if x < 2.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell(x) * self.mitchell(y)
+    }
+}
+
+/// Sinc windowed by a sinc as wide as the radius.
+pub struct LanczosFilter {
+    radius: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64) -> FilterPtr {
+        Arc::new(Self { radius })
+    }
+
+    fn lanczos(&self, v: f64) -> f64 {
+        let sinc = |x: f64| {
+            if x.abs() < 1e-5 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            }
+        };
+        if v.abs() < self.radius {
+            sinc(v) * sinc(v / self.radius)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.lanczos(x) * self.lanczos(y)
+    }
+}
+
+fn default_box_radius() -> f64 {
+    0.5
+}
+
+fn default_tent_radius() -> f64 {
+    1.0
+}
+
+fn default_gaussian_radius() -> f64 {
+    1.5
+}
+
+fn default_gaussian_alpha() -> f64 {
+    2.0
+}
+
+fn default_mitchell_radius() -> f64 {
+    2.0
+}
+
+fn default_mitchell_b() -> f64 {
+    1.0 / 3.0
+}
+
+fn default_mitchell_c() -> f64 {
+    1.0 / 3.0
+}
+
+fn default_lanczos_radius() -> f64 {
+    3.0
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum FilterDescription {
+    #[serde(rename = "box")]
+    Box {
+        #[serde(default = "default_box_radius")]
+        radius: f64,
+    },
+    #[serde(rename = "tent")]
+    Tent {
+        #[serde(default = "default_tent_radius")]
+        radius: f64,
+    },
+    #[serde(rename = "gaussian")]
+    Gaussian {
+        #[serde(default = "default_gaussian_radius")]
+        radius: f64,
+        /// falloff, larger values being sharper
+        #[serde(default = "default_gaussian_alpha")]
+        alpha: f64,
+    },
+    #[serde(rename = "mitchell")]
+    Mitchell {
+        #[serde(default = "default_mitchell_radius")]
+        radius: f64,
+        #[serde(default = "default_mitchell_b")]
+        b: f64,
+        #[serde(default = "default_mitchell_c")]
+        c: f64,
+    },
+    #[serde(rename = "lanczos")]
+    Lanczos {
+        #[serde(default = "default_lanczos_radius")]
+        radius: f64,
+    },
+}
+
+impl Default for FilterDescription {
+    /// one box per pixel, averaging the samples of each pixel
+    fn default() -> Self {
+        Self::Box {
+            radius: default_box_radius(),
+        }
+    }
+}
+
+impl FilterDescription {
+    pub fn set_radius(&mut self, r: f64) {
+        match self {
+            Self::Box { radius }
+            | Self::Tent { radius }
+            | Self::Gaussian { radius, .. }
+            | Self::Mitchell { radius, .. }
+            | Self::Lanczos { radius } => *radius = r,
+        }
+    }
+}
+
+/// The filter with its default parameters, from its name.
+impl FromStr for FilterDescription {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Self::default()),
+            "tent" => Ok(Self::Tent {
+                radius: default_tent_radius(),
+            }),
+            "gaussian" => Ok(Self::Gaussian {
+                radius: default_gaussian_radius(),
+                alpha: default_gaussian_alpha(),
+            }),
+            "mitchell" => Ok(Self::Mitchell {
+                radius: default_mitchell_radius(),
+                b: default_mitchell_b(),
+                c: default_mitchell_c(),
+            }),
+            "lanczos" => Ok(Self::Lanczos {
+                radius: default_lanczos_radius(),
+            }),
+            _ => Err(format!(
+                "unknown filter '{}' (expected box, tent, gaussian, mitchell or lanczos)",
+                s
+            )),
+        }
+    }
+}
+
+pub fn create_filter(desc: &FilterDescription) -> FilterPtr {
+    match *desc {
+        FilterDescription::Box { radius } => BoxFilter::new(radius),
+        FilterDescription::Tent { radius } => TentFilter::new(radius),
+        FilterDescription::Gaussian { radius, alpha } => GaussianFilter::new(radius, alpha),
+        FilterDescription::Mitchell { radius, b, c } => MitchellFilter::new(radius, b, c),
+        FilterDescription::Lanczos { radius } => LanczosFilter::new(radius),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_filter, FilterDescription};
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn filters_vanish_at_radius() {
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let f = create_filter(&name.parse::<FilterDescription>().unwrap());
+            let r = f.radius();
+            assert!(f.evaluate(0.0, 0.0) > 0.0);
+            assert_is_close(f.evaluate(r, 0.0), 0.0);
+            assert_is_close(f.evaluate(0.0, r + 0.1), 0.0);
+        }
+    }
+}
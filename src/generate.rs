@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::animation::{Interpolation, Keyframed};
+use crate::background::{BackgroundDescription, SolidColorDescription};
+use crate::camera::{CameraDescription, FocusDistance, Projection};
+use crate::material::{
+    DielectricDescription, DiffuseLightDescription, LambertianDescription, MaterialDescription,
+    MetalDescription,
+};
+use crate::objects::{ObjectDescription, QuadDescription, SphereDescription};
+use crate::scene::{FilmDescription, ObjectWithMaterialDescription, SceneDescription};
+use crate::utils::PI;
+use crate::vec3::{cross, unit_vector, Point3, Vec3};
+
+#[derive(Clone, Copy)]
+pub enum Template {
+    Cover,
+    CornellBox,
+    MaterialGrid,
+    SphereFlake,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cover" => Ok(Self::Cover),
+            "cornell_box" => Ok(Self::CornellBox),
+            "material_grid" => Ok(Self::MaterialGrid),
+            "sphere_flake" => Ok(Self::SphereFlake),
+            _ => Err(format!(
+                "unknown template '{}' (expected cover, cornell_box, material_grid or sphere_flake)",
+                s
+            )),
+        }
+    }
+}
+
+pub struct GeneratorParams {
+    /// template-dependent size: grid half extent for `cover`, spheres per side for
+    /// `material_grid`, recursion levels for `sphere_flake` (ignored by `cornell_box`)
+    pub size: Option<u32>,
+    /// seed for the random choices of the template
+    pub seed: u64,
+}
+
+/// deepest sphere flake, whose sphere count grows ninefold per level
+const MAX_FLAKE_LEVELS: u32 = 5;
+
+pub fn generate(template: Template, params: &GeneratorParams) -> Result<SceneDescription, String> {
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    Ok(match template {
+        Template::Cover => cover(params.size.unwrap_or(11) as i32, &mut rng),
+        Template::CornellBox => cornell_box(),
+        Template::MaterialGrid => material_grid(params.size.unwrap_or(5)),
+        Template::SphereFlake => {
+            let levels = params.size.unwrap_or(3);
+            if levels > MAX_FLAKE_LEVELS {
+                return Err(format!(
+                    "sphere_flake supports at most {} levels, got {}",
+                    MAX_FLAKE_LEVELS, levels
+                ));
+            }
+            sphere_flake(levels, &mut rng)
+        }
+    })
+}
+
+struct Builder {
+    materials: BTreeMap<String, MaterialDescription>,
+    world: Vec<ObjectWithMaterialDescription>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            materials: BTreeMap::new(),
+            world: vec![],
+        }
+    }
+
+    fn material(&mut self, name: &str, desc: MaterialDescription) {
+        self.materials.insert(name.to_owned(), desc);
+    }
+
+    fn object(&mut self, material: &str, desc: ObjectDescription) {
+        self.world.push(ObjectWithMaterialDescription {
+            name: None,
+            material: material.to_owned(),
+            keyframes: vec![],
+            desc,
+        });
+    }
+
+    fn sphere(&mut self, material: &str, center: Point3, radius: f64) {
+        self.object(
+            material,
+            ObjectDescription::Sphere(SphereDescription {
+                center: to_array(&center),
+                radius,
+            }),
+        );
+    }
+
+    fn quad(&mut self, material: &str, q: Point3, u: Vec3, v: Vec3) {
+        self.object(
+            material,
+            ObjectDescription::Quad(QuadDescription {
+                q: to_array(&q),
+                u: to_array(&u),
+                v: to_array(&v),
+            }),
+        );
+    }
+
+    /// axis-aligned box with outward facing sides
+    fn cuboid(&mut self, material: &str, a: Point3, b: Point3) {
+        let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        self.quad(material, Point3::new(min.x(), min.y(), max.z()), dx, dy); // front
+        self.quad(material, Point3::new(max.x(), min.y(), max.z()), -dz, dy); // right
+        self.quad(material, Point3::new(max.x(), min.y(), min.z()), -dx, dy); // back
+        self.quad(material, Point3::new(min.x(), min.y(), min.z()), dz, dy); // left
+        self.quad(material, Point3::new(min.x(), max.y(), max.z()), dx, -dz); // top
+        self.quad(material, Point3::new(min.x(), min.y(), min.z()), dx, dz); // bottom
+    }
+
+    fn build(
+        self,
+        camera: CameraDescription,
+        background: BackgroundDescription,
+    ) -> SceneDescription {
+        SceneDescription {
+            include: vec![],
+            materials: self.materials,
+            world: self.world,
+            lights: vec![],
+            atmosphere: None,
+            camera,
+            background,
+            film: FilmDescription::default(),
+        }
+    }
+}
+
+fn to_array(v: &Vec3) -> [f64; 3] {
+    [v.x(), v.y(), v.z()]
+}
+
+fn camera(
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    vfov: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    focus_dist: f64,
+) -> CameraDescription {
+    CameraDescription {
+        projection: Projection::Perspective,
+        lookfrom: Keyframed::Static(lookfrom),
+        lookat: Keyframed::Static(lookat),
+        vup: [0.0, 1.0, 0.0],
+        vfov: Keyframed::Static(vfov),
+        view_width: None,
+        aspect_ratio,
+        aperture,
+        aperture_shape: None,
+        focus_dist: FocusDistance::Fixed(Keyframed::Static(focus_dist)),
+        focus_on: None,
+        lens_effects: None,
+        interpolation: Interpolation::Linear,
+        physical: None,
+        orbit: None,
+        stereo: None,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    }
+}
+
+fn lambertian(albedo: [f64; 3]) -> MaterialDescription {
+    MaterialDescription::Lambertian(LambertianDescription { albedo })
+}
+
+fn metal(albedo: [f64; 3], fuzz: f64) -> MaterialDescription {
+    MaterialDescription::Metal(MetalDescription { albedo, fuzz })
+}
+
+fn dielectric(refraction: f64) -> MaterialDescription {
+    MaterialDescription::Dielectric(DielectricDescription {
+        refraction,
+        absorption: None,
+        dispersion: None,
+    })
+}
+
+/// the "random spheres" cover of Ray Tracing in One Weekend
+fn cover(size: i32, rng: &mut StdRng) -> SceneDescription {
+    let mut b = Builder::new();
+
+    // ground
+    b.material("ground", lambertian([0.5, 0.5, 0.5]));
+    b.sphere("ground", Point3::new(0.0, -1000.0, 0.0), 1000.0);
+
+    // random small spheres
+    for i in -size..size {
+        for j in -size..size {
+            let choose_mat = rng.gen::<f64>();
+            let center = Point3::new(
+                i as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                j as f64 + 0.9 * rng.gen::<f64>(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+
+            let m = if choose_mat < 0.8 {
+                let albedo = [0, 1, 2].map(|_| rng.gen::<f64>() * rng.gen::<f64>());
+                lambertian(albedo)
+            } else if choose_mat < 0.95 {
+                let albedo = [0, 1, 2].map(|_| rng.gen_range(0.5..1.0));
+                metal(albedo, rng.gen_range(0.0..0.5))
+            } else {
+                dielectric(1.5)
+            };
+            let name = format!("small_{}_{}", i, j);
+            b.material(&name, m);
+            b.sphere(&name, center, 0.2);
+        }
+    }
+
+    // big spheres
+    b.material("glass", dielectric(1.5));
+    b.sphere("glass", Point3::new(0.0, 1.0, 0.0), 1.0);
+    b.material("diffuse", lambertian([0.4, 0.2, 0.1]));
+    b.sphere("diffuse", Point3::new(-4.0, 1.0, 0.0), 1.0);
+    b.material("mirror", metal([0.7, 0.6, 0.5], 0.0));
+    b.sphere("mirror", Point3::new(4.0, 1.0, 0.0), 1.0);
+
+    b.build(
+        camera(
+            [13.0, 2.0, 3.0],
+            [0.0, 0.0, 0.0],
+            20.0,
+            3.0 / 2.0,
+            0.1,
+            10.0,
+        ),
+        BackgroundDescription::SkyGradient,
+    )
+}
+
+/// the classic Cornell box, lit by an area light on the ceiling
+fn cornell_box() -> SceneDescription {
+    let mut b = Builder::new();
+
+    b.material("red", lambertian([0.65, 0.05, 0.05]));
+    b.material("white", lambertian([0.73, 0.73, 0.73]));
+    b.material("green", lambertian([0.12, 0.45, 0.15]));
+    b.material(
+        "light",
+        MaterialDescription::DiffuseLight(DiffuseLightDescription {
+            emit: [15.0, 15.0, 15.0],
+        }),
+    );
+    b.material("glass", dielectric(1.5));
+
+    let x = Vec3::new(555.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 555.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 555.0);
+    let origin = Point3::zero();
+
+    // walls, facing inwards
+    b.quad("green", origin + x, z, y);
+    b.quad("red", origin, y, z);
+    b.quad("white", origin, z, x);
+    b.quad("white", origin + y, x, z);
+    b.quad("white", origin + z, y, x);
+
+    // ceiling light
+    b.quad(
+        "light",
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+    );
+
+    // contents
+    b.cuboid(
+        "white",
+        Point3::new(265.0, 0.0, 295.0),
+        Point3::new(430.0, 330.0, 460.0),
+    );
+    b.sphere("glass", Point3::new(190.0, 90.0, 190.0), 90.0);
+
+    b.build(
+        camera(
+            [278.0, 278.0, -800.0],
+            [278.0, 278.0, 0.0],
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+        ),
+        BackgroundDescription::Solid(SolidColorDescription {
+            color: [0.0, 0.0, 0.0],
+        }),
+    )
+}
+
+/// rows of lambertian, metal and dielectric spheres, with the main parameter
+/// of each material varying along the columns
+fn material_grid(size: u32) -> SceneDescription {
+    let mut b = Builder::new();
+    let n = size.max(1);
+
+    b.material("ground", lambertian([0.5, 0.5, 0.5]));
+    b.sphere("ground", Point3::new(0.0, -1000.0, 0.0), 1000.0);
+
+    for row in 0..n {
+        for col in 0..n {
+            let f = if n > 1 {
+                col as f64 / (n - 1) as f64
+            } else {
+                0.0
+            };
+            let m = match row % 3 {
+                0 => {
+                    // hue sweep
+                    let h = 2.0 * PI * f;
+                    lambertian([0.0, 2.0, 4.0].map(|o: f64| 0.5 + 0.4 * (h - o * PI / 3.0).cos()))
+                }
+                1 => metal([0.8, 0.8, 0.8], f),
+                _ => dielectric(1.0 + 1.5 * f),
+            };
+            let name = format!("grid_{}_{}", row, col);
+            b.material(&name, m);
+            b.sphere(
+                &name,
+                Point3::new(
+                    2.0 * col as f64 - (n - 1) as f64,
+                    0.8,
+                    (n - 1) as f64 - 2.0 * row as f64,
+                ),
+                0.8,
+            );
+        }
+    }
+
+    let extent = 2.0 * n as f64;
+    b.build(
+        camera(
+            [0.0, 1.2 * extent, 1.6 * extent],
+            [0.0, 0.0, 0.0],
+            30.0,
+            16.0 / 9.0,
+            0.0,
+            10.0,
+        ),
+        BackgroundDescription::SkyGradient,
+    )
+}
+
+/// Haines' sphere flake: every sphere carries nine children a third of its size
+fn sphere_flake(levels: u32, rng: &mut StdRng) -> SceneDescription {
+    let mut b = Builder::new();
+
+    b.material("ground", lambertian([0.5, 0.5, 0.5]));
+    b.sphere("ground", Point3::new(0.0, -1000.0, 0.0), 1000.0);
+
+    for level in 0..=levels {
+        let name = format!("level_{}", level);
+        let albedo = [0, 1, 2].map(|_| rng.gen_range(0.3..0.9));
+        if level % 2 == 0 {
+            b.material(&name, metal(albedo, rng.gen_range(0.0..0.2)));
+        } else {
+            b.material(&name, lambertian(albedo));
+        }
+    }
+
+    flake(
+        &mut b,
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Vec3::new(0.0, 1.0, 0.0),
+        0,
+        levels,
+    );
+
+    b.build(
+        camera(
+            [3.5, 2.8, 3.5],
+            [0.0, 1.0, 0.0],
+            35.0,
+            16.0 / 9.0,
+            0.0,
+            10.0,
+        ),
+        BackgroundDescription::SkyGradient,
+    )
+}
+
+fn flake(b: &mut Builder, center: Point3, radius: f64, axis: Vec3, level: u32, levels: u32) {
+    b.sphere(&format!("level_{}", level), center, radius);
+    if level == levels {
+        return;
+    }
+
+    // frame around the axis pointing away from the parent
+    let helper = if axis.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let u = unit_vector(&cross(&helper, &axis));
+    let v = cross(&axis, &u);
+
+    // six children around the equator and three above it
+    let child_radius = radius / 3.0;
+    let elevation = (1.0_f64 / 3.0).asin() + PI / 6.0;
+    let directions = (0..6)
+        .map(|k| (k as f64 * PI / 3.0, 0.0))
+        .chain((0..3).map(|k| (PI / 6.0 + k as f64 * 2.0 * PI / 3.0, elevation)));
+    for (azimuth, elev) in directions {
+        let dir = (u * azimuth.cos() + v * azimuth.sin()) * elev.cos() + axis * elev.sin();
+        flake(
+            b,
+            center + dir * (radius + child_radius),
+            child_radius,
+            dir,
+            level + 1,
+            levels,
+        );
+    }
+}
@@ -1,6 +1,8 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
 
+use crate::color::{color_to_pixel, Color};
+
 pub struct PixelColor {
     pub r: u8,
     pub g: u8,
@@ -8,18 +10,18 @@ pub struct PixelColor {
 }
 
 pub struct PixelCoord {
-    pub x: u16,
-    pub y: u16,
+    pub x: u32,
+    pub y: u32,
 }
 
 pub struct Image {
     data: Vec<u8>,
-    width: u16,
-    height: u16,
+    width: u32,
+    height: u32,
 }
 
 impl Image {
-    pub fn new(width: u16, height: u16) -> Self {
+    pub fn new(width: u32, height: u32) -> Self {
         let size = width as usize * height as usize * 3;
         Self {
             data: vec![0; size],
@@ -28,6 +30,20 @@ impl Image {
         }
     }
 
+    /// Gamma corrected image of HDR colors, given with rows going up from
+    /// the bottom.
+    pub fn from_colors(width: u32, height: u32, colors: &[Color]) -> Self {
+        let mut img = Self::new(width, height);
+        for (i, c) in colors.iter().enumerate() {
+            let p = PixelCoord {
+                x: (i % width as usize) as u32,
+                y: (i / width as usize) as u32,
+            };
+            img.set_color(&p, &color_to_pixel(c, 1));
+        }
+        img
+    }
+
     pub fn set_color(&mut self, p: &PixelCoord, c: &PixelColor) {
         let i = 3 * (p.y as usize * self.width as usize + p.x as usize);
         self.data[i] = c.r;
@@ -44,6 +60,19 @@ impl Image {
         }
     }
 
+    /// Copies `other` into this image, with its first pixel at `at`.
+    pub fn paste(&mut self, other: &Image, at: &PixelCoord) {
+        for p in other.iter() {
+            let q = PixelCoord {
+                x: at.x + p.x,
+                y: at.y + p.y,
+            };
+            if q.x < self.width && q.y < self.height {
+                self.set_color(&q, &other.get_color(&p));
+            }
+        }
+    }
+
     pub fn iter(&self) -> ImageIterator {
         ImageIterator {
             x: 0,
@@ -52,19 +81,19 @@ impl Image {
         }
     }
 
-    pub fn width(&self) -> u16 {
+    pub fn width(&self) -> u32 {
         self.width
     }
 
-    pub fn height(&self) -> u16 {
+    pub fn height(&self) -> u32 {
         self.height
     }
 }
 
 pub struct ImageIterator {
-    x: u16,
-    y: u16,
-    width: u16,
+    x: u32,
+    y: u32,
+    width: u32,
 }
 
 impl Iterator for ImageIterator {
@@ -89,19 +118,160 @@ impl Iterator for ImageIterator {
 pub fn save_ppm(file_name: &str, image: &Image) -> Result<(), String> {
     print!("Saving file... ");
     io::stdout().flush().unwrap();
-    let full_name = file_name.to_owned() + ".ppm";
-    let file = match File::create(full_name) {
-        io::Result::Ok(v) => v,
-        io::Result::Err(e) => return Err(e.to_string()),
-    };
-    let mut buf = BufWriter::new(file);
-    writeln!(buf, "P3").unwrap();
-    writeln!(buf, "{} {}", image.width, image.height).unwrap();
-    writeln!(buf, "255").unwrap();
-    for p in image.iter() {
-        let c = image.get_color(&p);
-        writeln!(buf, "{} {} {}", c.r, c.g, c.b).unwrap();
-    }
+    let mut writer = PpmWriter::create(file_name, image.width, image.height)?;
+    writer.write_rows(image)?;
+    writer.finish()?;
     println!("done!");
     Ok(())
 }
+
+/// Writes a PPM image a strip of rows at a time from the top, so that the
+/// whole image never has to be held in memory.
+pub struct PpmWriter {
+    buf: BufWriter<File>,
+}
+
+impl PpmWriter {
+    pub fn create(file_name: &str, width: u32, height: u32) -> Result<Self, String> {
+        let full_name = file_name.to_owned() + ".ppm";
+        let file = File::create(full_name).map_err(|e| e.to_string())?;
+        let mut buf = BufWriter::new(file);
+        write!(buf, "P3\n{} {}\n255\n", width, height).map_err(|e| e.to_string())?;
+        Ok(Self { buf })
+    }
+
+    /// appends the rows of an image as wide as the file
+    pub fn write_rows(&mut self, image: &Image) -> Result<(), String> {
+        for p in image.iter() {
+            let c = image.get_color(&p);
+            writeln!(self.buf, "{} {} {}", c.r, c.g, c.b).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        self.buf.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Saves floating point data as a PFM image, with rows going up from the
+/// bottom as in the file, keeping only the first channel if `grayscale`.
+pub fn save_pfm(
+    file_name: &str,
+    width: u32,
+    height: u32,
+    data: &[Color],
+    grayscale: bool,
+) -> Result<(), String> {
+    let full_name = file_name.to_owned() + ".pfm";
+    let file = File::create(full_name).map_err(|e| e.to_string())?;
+    let mut buf = BufWriter::new(file);
+    let magic = if grayscale { "Pf" } else { "PF" };
+    // a negative scale stands for little endian values
+    write!(buf, "{}\n{} {}\n-1.0\n", magic, width, height).map_err(|e| e.to_string())?;
+    for c in data {
+        let channels = if grayscale { 1 } else { 3 };
+        for k in 0..channels {
+            buf.write_all(&(c[k] as f32).to_le_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    buf.flush().map_err(|e| e.to_string())
+}
+
+/// Reads a binary (P6) or plain (P3) PPM image with 8-bit channels.
+pub fn load_ppm(file_name: &str) -> Result<Image, String> {
+    let data = fs::read(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    let err = |msg: &str| format!("{}: {}", file_name, msg);
+
+    // header: magic number, width, height and maximum value, with comments
+    let mut pos = 0;
+    let mut tokens = vec![];
+    while tokens.len() < 4 {
+        while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+            if data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(err("truncated header"));
+        }
+        tokens.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+    let width: u32 = tokens[1].parse().map_err(|_| err("invalid width"))?;
+    let height: u32 = tokens[2].parse().map_err(|_| err("invalid height"))?;
+    let maxval: u16 = tokens[3]
+        .parse()
+        .map_err(|_| err("invalid maximum value"))?;
+    if maxval == 0 || maxval > 255 {
+        return Err(err("only 8-bit images are supported"));
+    }
+
+    let size = width as usize * height as usize * 3;
+    let values: Vec<u8> = match tokens[0].as_str() {
+        "P6" => data
+            .get(pos + 1..pos + 1 + size)
+            .ok_or_else(|| err("truncated data"))?
+            .to_vec(),
+        "P3" => String::from_utf8_lossy(&data[pos..])
+            .split_ascii_whitespace()
+            .take(size)
+            .map(|v| v.parse::<u8>().map_err(|_| err("invalid value")))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(err("not a PPM image")),
+    };
+    if values.len() < size {
+        return Err(err("truncated data"));
+    }
+
+    let scale = |v: u8| (v as u32 * 255 / maxval as u32) as u8;
+    let mut img = Image::new(width, height);
+    for (p, c) in img.iter().zip(values.chunks(3)) {
+        img.set_color(
+            &p,
+            &PixelColor {
+                r: scale(c[0]),
+                g: scale(c[1]),
+                b: scale(c[2]),
+            },
+        );
+    }
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_ppm, save_ppm, Image, PixelColor, PixelCoord};
+
+    #[test]
+    fn ppm_roundtrip() {
+        let mut img = Image::new(3, 2);
+        img.set_color(
+            &PixelCoord { x: 2, y: 1 },
+            &PixelColor {
+                r: 10,
+                g: 20,
+                b: 30,
+            },
+        );
+        let name = std::env::temp_dir().join("rusty_rays_ppm_roundtrip");
+        let name = name.to_str().unwrap();
+        save_ppm(name, &img).unwrap();
+
+        let loaded = load_ppm(&(name.to_owned() + ".ppm")).unwrap();
+        assert_eq!(loaded.width(), 3);
+        assert_eq!(loaded.height(), 2);
+        let c = loaded.get_color(&PixelCoord { x: 2, y: 1 });
+        assert_eq!((c.r, c.g, c.b), (10, 20, 30));
+        let c = loaded.get_color(&PixelCoord { x: 0, y: 0 });
+        assert_eq!((c.r, c.g, c.b), (0, 0, 0));
+    }
+}
@@ -1,10 +1,28 @@
+#![allow(clippy::new_ret_no_self)]
+
+pub mod aabb;
+pub mod animation;
+pub mod aov;
+pub mod background;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod generate;
 pub mod image;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
 pub mod objects;
+pub mod preview;
 pub mod ray;
 pub mod render;
 pub mod scene;
+pub mod spectrum;
+pub mod texture;
 pub mod utils;
 pub mod vec3;
+pub mod watch;
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::Color,
+    utils::{deg_to_rad, INFINITY},
+    vec3::{dot, unit_vector, Point3, Vec3},
+};
+
+/// Light arriving at a point from a punctual light.
+pub struct LightSample {
+    /// unit direction towards the light
+    pub wi: Vec3,
+    /// distance to the light, infinite for directional lights
+    pub distance: f64,
+    /// irradiance on a surface facing the light
+    pub irradiance: Color,
+}
+
+/// Lights without geometry, reached from a point through a single
+/// direction and so lighting it without noise.
+pub trait Light: Sync + Send {
+    /// light reaching `p`, or `None` if the light does not shine on it
+    fn sample(&self, p: &Point3) -> Option<LightSample>;
+}
+
+pub type LightPtr = Arc<dyn Light>;
+
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PointLightDescription {
+    pub position: [f64; 3],
+    /// power per solid angle
+    pub intensity: [f64; 3],
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> LightPtr {
+        Arc::new(Self {
+            position,
+            intensity,
+        })
+    }
+
+    pub fn from(desc: &PointLightDescription) -> LightPtr {
+        Self::new(
+            Point3::new(desc.position[0], desc.position[1], desc.position[2]),
+            Color::new(desc.intensity[0], desc.intensity[1], desc.intensity[2]),
+        )
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let d = self.position - *p;
+        let distance = d.length();
+        Some(LightSample {
+            wi: d / distance,
+            distance,
+            irradiance: self.intensity / (distance * distance),
+        })
+    }
+}
+
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    /// cosines of the half angles of the cone and of its fully lit core
+    cos_outer: f64,
+    cos_inner: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SpotLightDescription {
+    pub position: [f64; 3],
+    /// direction the light shines in
+    pub direction: [f64; 3],
+    /// power per solid angle within the cone
+    pub intensity: [f64; 3],
+    /// half angle of the cone in degrees
+    pub angle: f64,
+    /// angle in degrees over which the edge of the cone fades out
+    #[serde(default)]
+    pub falloff: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        angle: f64,
+        falloff: f64,
+    ) -> LightPtr {
+        let outer = deg_to_rad(angle.clamp(0.0, 180.0));
+        let inner = (outer - deg_to_rad(falloff.max(0.0))).max(0.0);
+        Arc::new(Self {
+            position,
+            direction: unit_vector(&direction),
+            intensity,
+            cos_outer: outer.cos(),
+            cos_inner: inner.cos(),
+        })
+    }
+
+    pub fn from(desc: &SpotLightDescription) -> LightPtr {
+        Self::new(
+            Point3::new(desc.position[0], desc.position[1], desc.position[2]),
+            Vec3::new(desc.direction[0], desc.direction[1], desc.direction[2]),
+            Color::new(desc.intensity[0], desc.intensity[1], desc.intensity[2]),
+            desc.angle,
+            desc.falloff,
+        )
+    }
+
+    /// smooth fade from the core of the cone to its edge
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let d = self.position - *p;
+        let distance = d.length();
+        let wi = d / distance;
+        let falloff = self.falloff(dot(&-wi, &self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            distance,
+            irradiance: self.intensity * (falloff / (distance * distance)),
+        })
+    }
+}
+
+pub struct DirectionalLight {
+    /// unit direction towards the light
+    to_light: Vec3,
+    irradiance: Color,
+    /// cosine of the half angle the light subtends
+    cos_theta_max: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DirectionalLightDescription {
+    /// direction the light travels in
+    pub direction: [f64; 3],
+    /// irradiance on a surface facing the light
+    pub irradiance: [f64; 3],
+    /// apparent size of the light in degrees, 0.53 for the sun, softening the
+    /// shadows it casts
+    #[serde(default)]
+    pub angular_diameter: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color, angular_diameter: f64) -> LightPtr {
+        Arc::new(Self {
+            to_light: -unit_vector(&direction),
+            irradiance,
+            cos_theta_max: deg_to_rad(angular_diameter.clamp(0.0, 180.0) / 2.0).cos(),
+        })
+    }
+
+    pub fn from(desc: &DirectionalLightDescription) -> LightPtr {
+        Self::new(
+            Vec3::new(desc.direction[0], desc.direction[1], desc.direction[2]),
+            Color::new(desc.irradiance[0], desc.irradiance[1], desc.irradiance[2]),
+            desc.angular_diameter,
+        )
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3) -> Option<LightSample> {
+        let wi = if self.cos_theta_max < 1.0 {
+            Vec3::random_in_cone(&self.to_light, self.cos_theta_max)
+        } else {
+            self.to_light
+        };
+        Some(LightSample {
+            wi,
+            distance: INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LightDescription {
+    #[serde(rename = "point")]
+    Point(PointLightDescription),
+    #[serde(rename = "spot")]
+    Spot(SpotLightDescription),
+    #[serde(rename = "directional", alias = "sun")]
+    Directional(DirectionalLightDescription),
+}
+
+pub fn create_light(desc: &LightDescription) -> LightPtr {
+    match desc {
+        LightDescription::Point(d) => PointLight::from(d),
+        LightDescription::Spot(d) => SpotLight::from(d),
+        LightDescription::Directional(d) => DirectionalLight::from(d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpotLight;
+    use crate::color::Color;
+    use crate::vec3::{Point3, Vec3};
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        let light = SpotLight::new(
+            Point3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Color::new(4.0, 4.0, 4.0),
+            30.0,
+            10.0,
+        );
+        // inverse square within the core, nothing outside of the cone
+        let center = light.sample(&Point3::zero()).unwrap();
+        assert_is_close(center.irradiance.x(), 1.0);
+        assert_is_close(center.distance, 2.0);
+        assert!(light.sample(&Point3::new(2.0, 0.0, 0.0)).is_none());
+        // halfway through the edge, at 25 degrees
+        let x = 2.0 * 25f64.to_radians().tan();
+        let edge = light.sample(&Point3::new(x, 0.0, 0.0)).unwrap();
+        assert!(edge.irradiance.x() > 0.0 && edge.irradiance.x() < 4.0 / (4.0 + x * x));
+    }
+}
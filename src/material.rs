@@ -3,25 +3,79 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    color::Color,
+    color::{luminance, Color},
+    microfacet::{fresnel_conductor, schlick, Frame, Ggx},
     objects::HitRecord,
-    ray::Ray,
-    utils::random,
+    texture::{create_texture, texture_value, TextureDescription, TexturePtr},
+    utils::{random, PI},
     vec3::{dot, reflect, unit_vector, Vec3},
 };
 
-pub struct Scattered {
-    pub attenuation: Color,
-    pub ray: Ray,
+/// Direction sampled by a material, with directions pointing away from the
+/// surface.
+pub struct BsdfSample {
+    pub wi: Vec3,
+    /// BSDF times the cosine between `wi` and the normal
+    pub value: Color,
+    /// density over solid angle, or for delta lobes the probability of
+    /// picking the lobe
+    pub pdf: f64,
+    /// specular lobe, which `eval` and `pdf` leave out
+    pub delta: bool,
 }
 
-pub enum RayScatter {
-    Scatter(Scattered),
-    NoScatter,
+impl BsdfSample {
+    /// factor of the light arriving from `wi` in the estimate
+    pub fn weight(&self) -> Color {
+        self.value / self.pdf
+    }
 }
 
+/// Scattering of light by a surface, from the direction `wi` it arrives
+/// from towards `wo`, both unit vectors pointing away from the hit point.
 pub trait Material: Sync + Send {
-    fn scatter(&self, r: &Ray, rec: &HitRecord) -> RayScatter;
+    /// BSDF times the cosine between `wi` and the normal, leaving out the
+    /// delta lobes
+    fn eval(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> Color {
+        Color::zero()
+    }
+
+    /// direction light arrives from, picked for the outgoing direction `wo`,
+    /// or `None` if the light is absorbed
+    fn sample(&self, _rec: &HitRecord, _wo: &Vec3) -> Option<BsdfSample> {
+        None
+    }
+
+    /// density with which `sample` picks `wi`, leaving out the delta lobes
+    fn pdf(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    /// surface color, for the albedo output pass
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    /// emissive materials make their objects lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// materials scattering each wavelength in its own direction, which
+    /// spectral paths then follow for their hero wavelength alone
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// absorption coefficients per unit distance within the closed objects
+    /// made of the material, dimming the rays crossing them
+    fn absorption(&self) -> Option<Color> {
+        None
+    }
 }
 
 pub type MaterialPtr = Arc<dyn Material>;
@@ -32,7 +86,7 @@ pub struct Lambertian {
 
 #[derive(Serialize, Deserialize)]
 pub struct LambertianDescription {
-    albedo: [f64; 3],
+    pub albedo: [f64; 3],
 }
 
 impl Lambertian {
@@ -46,16 +100,32 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, rec: &HitRecord) -> RayScatter {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-        if scatter_direction.is_near_zero() {
-            scatter_direction = rec.normal;
-        }
-        RayScatter::Scatter(Scattered {
-            attenuation: self.albedo,
-            ray: Ray::new(rec.p, scatter_direction),
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, _wo: &Vec3) -> Color {
+        self.albedo * (dot(&rec.normal, wi).max(0.0) / PI)
+    }
+
+    /// cosine weighted, as the directions `normal + random_unit_vector()`
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let mut direction = rec.normal + Vec3::random_unit_vector();
+        if direction.is_near_zero() {
+            direction = rec.normal;
+        }
+        let wi = unit_vector(&direction);
+        Some(BsdfSample {
+            wi,
+            value: self.eval(rec, &wi, wo),
+            pdf: self.pdf(rec, &wi, wo),
+            delta: false,
         })
     }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, _wo: &Vec3) -> f64 {
+        dot(&rec.normal, wi).max(0.0) / PI
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 pub struct Metal {
@@ -65,8 +135,8 @@ pub struct Metal {
 
 #[derive(Serialize, Deserialize)]
 pub struct MetalDescription {
-    albedo: [f64; 3],
-    fuzz: f64,
+    pub albedo: [f64; 3],
+    pub fuzz: f64,
 }
 
 impl Metal {
@@ -85,71 +155,819 @@ impl Metal {
     }
 }
 
+impl Metal {
+    /// Density of the direction `wi` among the mirror direction moved by a
+    /// random point of the ball of radius `fuzz`: the volume of the ball
+    /// along the ray towards `wi`, weighted by the squared distance.
+    fn fuzz_pdf(&self, reflected: &Vec3, wi: &Vec3) -> f64 {
+        let f = self.fuzz;
+        let b = dot(wi, reflected);
+        let discriminant = b * b - (reflected.length_squared() - f * f);
+        if discriminant < 0.0 {
+            return 0.0;
+        }
+        let s0 = (b - discriminant.sqrt()).max(0.0);
+        let s1 = (b + discriminant.sqrt()).max(0.0);
+        (s1.powi(3) - s0.powi(3)) / (4.0 * PI * f.powi(3))
+    }
+}
+
 impl Material for Metal {
-    fn scatter(&self, r: &Ray, rec: &HitRecord) -> RayScatter {
-        let reflected = reflect(&unit_vector(&r.direction()), &rec.normal);
-        let dir = reflected + Vec3::random_in_unit_sphere() * self.fuzz;
-        if dot(&dir, &rec.normal) > 0.0 {
-            RayScatter::Scatter(Scattered {
-                attenuation: self.albedo,
-                ray: Ray::new(rec.p, dir),
-            })
-        } else {
-            RayScatter::NoScatter
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        // the sampled directions are weighted by the albedo alone
+        self.albedo * self.pdf(rec, wi, wo)
+    }
+
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let reflected = reflect(&-*wo, &rec.normal);
+        let wi = unit_vector(&(reflected + Vec3::random_in_unit_sphere() * self.fuzz));
+        if dot(&wi, &rec.normal) <= 0.0 {
+            return None;
+        }
+        if self.fuzz == 0.0 {
+            return Some(BsdfSample {
+                wi,
+                value: self.albedo,
+                pdf: 1.0,
+                delta: true,
+            });
         }
+        Some(BsdfSample {
+            wi,
+            value: self.eval(rec, &wi, wo),
+            pdf: self.pdf(rec, &wi, wo),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        if self.fuzz == 0.0 || dot(wi, &rec.normal) <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(&reflect(&-*wo, &rec.normal), wi)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
     }
 }
 
 pub struct Dielectric {
     ir: f64,
+    /// absorption coefficients of the medium inside, per unit distance
+    sigma_a: Color,
+    dispersion: Option<Dispersion>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DielectricDescription {
-    refraction: f64,
+    pub refraction: f64,
+    /// colored medium inside, clear if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub absorption: Option<AbsorptionDescription>,
+    /// variation of the index of refraction with the wavelength
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispersion: Option<Dispersion>,
+}
+
+/// Color of white light after travelling `distance` through a medium.
+#[derive(Serialize, Deserialize)]
+pub struct AbsorptionDescription {
+    pub color: [f64; 3],
+    #[serde(default = "default_absorption_distance")]
+    pub distance: f64,
+}
+
+fn default_absorption_distance() -> f64 {
+    1.0
+}
+
+/// Variation of the index of refraction of a dielectric with the wavelength
+/// `λ` in micrometers, around its index at the sodium d line (587.6 nm),
+/// which colors are rendered with. Only spectral rendering disperses light.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Dispersion {
+    /// Cauchy's equation, `A + B / λ²`
+    #[serde(rename = "cauchy")]
+    Cauchy { b: f64 },
+    /// Sellmeier's equation, `n² = 1 + Σ B λ² / (λ² - C)`
+    #[serde(rename = "sellmeier")]
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// index at `lambda` in nanometers of a dielectric of index `ir` at the
+    /// d line
+    pub fn index_of_refraction(&self, ir: f64, lambda: f64) -> f64 {
+        let n = |lambda: f64| {
+            let l2 = lambda * lambda;
+            match self {
+                Dispersion::Cauchy { b } => b / l2,
+                Dispersion::Sellmeier { b, c } => {
+                    (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+                }
+            }
+        };
+        ir + n(lambda / 1000.0) - n(0.5876)
+    }
 }
 
 impl Dielectric {
-    pub fn new(index_of_refraction: f64) -> MaterialPtr {
+    /// Glass whose inside turns white light into `absorption_color` over
+    /// `distance`, following the Beer-Lambert law.
+    pub fn new(
+        index_of_refraction: f64,
+        absorption_color: Color,
+        distance: f64,
+        dispersion: Option<Dispersion>,
+    ) -> MaterialPtr {
+        let sigma_a = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance.max(1e-6);
         Arc::new(Self {
             ir: index_of_refraction,
+            sigma_a: Color::new(
+                sigma_a(absorption_color.x()),
+                sigma_a(absorption_color.y()),
+                sigma_a(absorption_color.z()),
+            ),
+            dispersion,
         })
     }
 
     pub fn from(desc: &DielectricDescription) -> MaterialPtr {
-        Self::new(desc.refraction)
+        match &desc.absorption {
+            Some(a) => Self::new(
+                desc.refraction,
+                Color::new(a.color[0], a.color[1], a.color[2]),
+                a.distance,
+                desc.dispersion,
+            ),
+            None => Self::new(
+                desc.refraction,
+                Color::new(1.0, 1.0, 1.0),
+                1.0,
+                desc.dispersion,
+            ),
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r: &Ray, rec: &HitRecord) -> RayScatter {
-        let refraction_ratio = if rec.front_face {
-            (1.0) / self.ir
-        } else {
-            self.ir
+    /// reflection and refraction, both delta lobes picked by their
+    /// Fresnel reflectance
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let ir = match (&self.dispersion, rec.wavelength) {
+            (Some(d), Some(lambda)) => d.index_of_refraction(self.ir, lambda),
+            _ => self.ir,
         };
-        let unit_direction = unit_vector(&r.direction());
+        let refraction_ratio = if rec.front_face { (1.0) / ir } else { ir };
+        let unit_direction = -*wo;
         let cos_theta = dot(&-unit_direction, &rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
         let refractance = {
             let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
             r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
         };
-        let direction = if refraction_ratio * sin_theta > 1.0 || refractance > random() {
-            reflect(&unit_direction, &rec.normal)
+        let reflectance = if refraction_ratio * sin_theta > 1.0 {
+            1.0
+        } else {
+            refractance
+        };
+        let (wi, pdf) = if reflectance > random() {
+            (reflect(&unit_direction, &rec.normal), reflectance)
         } else {
             let uv = &unit_direction;
             let n = &rec.normal;
             let r_out_perp = (*uv + *n * cos_theta) * refraction_ratio;
             let r_out_parallel = -*n * (1.0 - r_out_perp.length_squared()).sqrt();
-            r_out_perp + r_out_parallel
+            (r_out_perp + r_out_parallel, 1.0 - reflectance)
+        };
+
+        Some(BsdfSample {
+            wi: unit_vector(&wi),
+            value: Color::new(1.0, 1.0, 1.0) * pdf,
+            pdf,
+            delta: true,
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        let clear = self.sigma_a.x() == 0.0 && self.sigma_a.y() == 0.0 && self.sigma_a.z() == 0.0;
+        (!clear).then_some(self.sigma_a)
+    }
+}
+
+/// Metal with a rough surface of GGX microfacets, reflecting by the Fresnel
+/// equations of its complex index of refraction.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    ggx: Ggx,
+}
+
+/// Measured indices of refraction of common metals, at the wavelengths of
+/// the red, green and blue primaries.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+}
+
+impl ConductorPreset {
+    /// real and imaginary parts of the index of refraction
+    pub fn ior(&self) -> ([f64; 3], [f64; 3]) {
+        match self {
+            ConductorPreset::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            ConductorPreset::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            ConductorPreset::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConductorIorDescription {
+    Preset { preset: ConductorPreset },
+    Ior { eta: [f64; 3], k: [f64; 3] },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConductorDescription {
+    #[serde(flatten)]
+    pub ior: ConductorIorDescription,
+    /// from 0 for a mirror to 1
+    pub roughness: f64,
+    /// stretch of the highlights along the tangent, from 0 to 1
+    #[serde(default)]
+    pub anisotropy: f64,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> MaterialPtr {
+        Arc::new(Self {
+            eta,
+            k,
+            ggx: Ggx::new(roughness, anisotropy),
+        })
+    }
+
+    pub fn from(desc: &ConductorDescription) -> MaterialPtr {
+        let (eta, k) = match &desc.ior {
+            ConductorIorDescription::Preset { preset } => preset.ior(),
+            ConductorIorDescription::Ior { eta, k } => (*eta, *k),
+        };
+        Self::new(
+            Color::new(eta[0], eta[1], eta[2]),
+            Color::new(k[0], k[1], k[2]),
+            desc.roughness,
+            desc.anisotropy,
+        )
+    }
+
+    fn fresnel(&self, cos_i: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_i, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_i, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_i, self.eta.z(), self.k.z()),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        let frame = Frame::new(&rec.normal);
+        match self
+            .ggx
+            .reflection(&frame.to_local(wi), &frame.to_local(wo))
+        {
+            Some((value, _, cos)) => self.fresnel(cos) * value,
+            None => Color::zero(),
+        }
+    }
+
+    /// reflection on a microfacet visible from `wo`
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&rec.normal);
+        let wi = frame.to_world(&self.ggx.sample_reflection(&frame.to_local(wo))?);
+        Some(BsdfSample {
+            wi,
+            value: self.eval(rec, &wi, wo),
+            pdf: self.pdf(rec, &wi, wo),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame = Frame::new(&rec.normal);
+        self.ggx
+            .reflection(&frame.to_local(wi), &frame.to_local(wo))
+            .map_or(0.0, |(_, pdf, _)| pdf)
+    }
+
+    /// reflectance at normal incidence
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.fresnel(1.0)
+    }
+}
+
+/// Glass with a rough surface of GGX microfacets, reflecting and
+/// transmitting light (Walter et al. 2007).
+pub struct RoughDielectric {
+    ir: f64,
+    ggx: Ggx,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoughDielectricDescription {
+    pub refraction: f64,
+    /// from 0 for smooth glass to 1
+    pub roughness: f64,
+    /// stretch of the highlights along the tangent, from 0 to 1
+    #[serde(default)]
+    pub anisotropy: f64,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: f64, anisotropy: f64) -> MaterialPtr {
+        Arc::new(Self {
+            ir: index_of_refraction,
+            ggx: Ggx::new(roughness, anisotropy),
+        })
+    }
+
+    pub fn from(desc: &RoughDielectricDescription) -> MaterialPtr {
+        Self::new(desc.refraction, desc.roughness, desc.anisotropy)
+    }
+
+    /// ratio of the index of refraction across the surface to the one on
+    /// the side of the normal
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        let frame = Frame::new(&rec.normal);
+        let (value, _) =
+            self.ggx
+                .dielectric(self.eta(rec), &frame.to_local(wi), &frame.to_local(wo));
+        Color::new(value, value, value)
+    }
+
+    /// reflection or refraction on a microfacet visible from `wo`, picked
+    /// by its Fresnel reflectance
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&rec.normal);
+        let wo_local = frame.to_local(wo);
+        let eta = self.eta(rec);
+        let wi = self.ggx.sample_dielectric(eta, &wo_local)?;
+        let (value, pdf) = self.ggx.dielectric(eta, &wi, &wo_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi: frame.to_world(&wi),
+            value: Color::new(value, value, value),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame = Frame::new(&rec.normal);
+        let (_, pdf) = self
+            .ggx
+            .dielectric(self.eta(rec), &frame.to_local(wi), &frame.to_local(wo));
+        pdf
+    }
+}
+
+/// Layered material of the parameters artists use in other PBR tools,
+/// after the Disney principled BSDF: a diffuse base with sheen, blended
+/// into a GGX metal by `metallic` and into rough glass by `transmission`,
+/// under a clear coat. Every parameter is a texture.
+pub struct Principled {
+    base_color: TexturePtr,
+    metallic: TexturePtr,
+    roughness: TexturePtr,
+    anisotropy: TexturePtr,
+    specular: TexturePtr,
+    sheen: TexturePtr,
+    sheen_tint: TexturePtr,
+    clearcoat: TexturePtr,
+    clearcoat_roughness: TexturePtr,
+    transmission: TexturePtr,
+    ior: TexturePtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PrincipledDescription {
+    #[serde(default = "default_base_color")]
+    pub base_color: TextureDescription,
+    /// blend from a dielectric to a metal of the base color
+    #[serde(default = "default_zero")]
+    pub metallic: TextureDescription,
+    #[serde(default = "default_roughness")]
+    pub roughness: TextureDescription,
+    /// stretch of the highlights around the y axis, from 0 to 1
+    #[serde(default = "default_zero")]
+    pub anisotropy: TextureDescription,
+    /// reflectance of dielectrics at normal incidence, 0.5 for 4%
+    #[serde(default = "default_specular")]
+    pub specular: TextureDescription,
+    /// soft reflection at grazing angles, for cloth
+    #[serde(default = "default_zero")]
+    pub sheen: TextureDescription,
+    /// blend of the sheen from white to the base color
+    #[serde(default = "default_sheen_tint")]
+    pub sheen_tint: TextureDescription,
+    /// second specular layer on top, for varnish
+    #[serde(default = "default_zero")]
+    pub clearcoat: TextureDescription,
+    #[serde(default = "default_clearcoat_roughness")]
+    pub clearcoat_roughness: TextureDescription,
+    /// blend of the dielectric into glass tinted by the base color
+    #[serde(default = "default_zero")]
+    pub transmission: TextureDescription,
+    /// index of refraction of the glass
+    #[serde(default = "default_ior")]
+    pub ior: TextureDescription,
+}
+
+fn default_base_color() -> TextureDescription {
+    TextureDescription::Color([0.8, 0.8, 0.8])
+}
+
+fn default_zero() -> TextureDescription {
+    TextureDescription::Value(0.0)
+}
+
+fn default_roughness() -> TextureDescription {
+    TextureDescription::Value(0.5)
+}
+
+fn default_specular() -> TextureDescription {
+    TextureDescription::Value(0.5)
+}
+
+fn default_sheen_tint() -> TextureDescription {
+    TextureDescription::Value(0.5)
+}
+
+fn default_clearcoat_roughness() -> TextureDescription {
+    TextureDescription::Value(0.03)
+}
+
+fn default_ior() -> TextureDescription {
+    TextureDescription::Value(1.5)
+}
+
+impl PrincipledDescription {
+    /// every parameter
+    pub fn textures_mut(&mut self) -> [&mut TextureDescription; 11] {
+        [
+            &mut self.base_color,
+            &mut self.metallic,
+            &mut self.roughness,
+            &mut self.anisotropy,
+            &mut self.specular,
+            &mut self.sheen,
+            &mut self.sheen_tint,
+            &mut self.clearcoat,
+            &mut self.clearcoat_roughness,
+            &mut self.transmission,
+            &mut self.ior,
+        ]
+    }
+}
+
+/// Parameters of `Principled` at a hit, and the lobes they make.
+struct PrincipledLobes {
+    base_color: Color,
+    metallic: f64,
+    specular: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    transmission: f64,
+    eta: f64,
+    roughness: f64,
+    ggx: Ggx,
+    clearcoat_ggx: Ggx,
+}
+
+impl PrincipledLobes {
+    /// reflectance of the specular layer at normal incidence
+    fn f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular;
+        Color::new(dielectric, dielectric, dielectric) * (1.0 - self.metallic)
+            + self.base_color * self.metallic
+    }
+
+    /// weights of the diffuse, specular, transmission and clear coat lobes
+    fn weights(&self) -> [f64; 4] {
+        let diffuse = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let transmission = (1.0 - self.metallic) * self.transmission;
+        [diffuse, 1.0 - transmission, transmission, self.clearcoat]
+    }
+
+    /// probabilities of sampling the lobes
+    fn probabilities(&self) -> [f64; 4] {
+        let [d, s, t, c] = self.weights();
+        let w = [d * luminance(&self.base_color).max(0.1), s, t, 0.5 * c];
+        let total: f64 = w.iter().sum();
+        w.map(|x| x / total)
+    }
+
+    /// BSDF times the cosine of `wi` and density of `sample`, in the local
+    /// frame
+    fn eval_pdf(&self, wi: &Vec3, wo: &Vec3) -> (Color, f64) {
+        if wo.z() <= 0.0 {
+            return (Color::zero(), 0.0);
+        }
+        let [kd, ks, kt, kc] = self.weights();
+        let [pd, ps, pt, pc] = self.probabilities();
+        let mut value = Color::zero();
+        let mut pdf = 0.0;
+
+        // the clear coat takes its reflection from the layers beneath
+        let coat = 1.0 - kc * schlick(wo.z(), 0.04);
+
+        if wi.z() > 0.0 {
+            let h = unit_vector(&(*wi + *wo));
+            let cos_d = dot(wi, &h);
+
+            // Burley diffuse with retro-reflection and sheen
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fl = 1.0 + (fd90 - 1.0) * (1.0 - wi.z()).powi(5);
+            let fv = 1.0 + (fd90 - 1.0) * (1.0 - wo.z()).powi(5);
+            let tint = self.base_color / luminance(&self.base_color).max(1e-3);
+            let sheen_color =
+                Color::new(1.0, 1.0, 1.0) * (1.0 - self.sheen_tint) + tint * self.sheen_tint;
+            let sheen = sheen_color * (self.sheen * (1.0 - cos_d).powi(5));
+            value += (self.base_color * (fl * fv / PI) + sheen) * (kd * coat * wi.z());
+            pdf += pd * wi.z() / PI;
+
+            if let Some((v, p, cos)) = self.ggx.reflection(wi, wo) {
+                let f0 = self.f0();
+                let fresnel = Color::new(
+                    schlick(cos, f0.x()),
+                    schlick(cos, f0.y()),
+                    schlick(cos, f0.z()),
+                );
+                value += fresnel * (v * ks * coat);
+                pdf += ps * p;
+            }
+            if let Some((v, p, cos)) = self.clearcoat_ggx.reflection(wi, wo) {
+                let f = schlick(cos, 0.04) * v * kc;
+                value += Color::new(f, f, f);
+                pdf += pc * p;
+            }
+        }
+
+        if kt > 0.0 {
+            // glass reflects white and transmits the base color
+            let (v, p) = self.ggx.dielectric(self.eta, wi, wo);
+            let tint = if wi.z() < 0.0 {
+                self.base_color
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            value += tint * (v * kt * coat);
+            pdf += pt * p;
+        }
+        (value, pdf)
+    }
+}
+
+impl Principled {
+    pub fn from(desc: &PrincipledDescription) -> Result<MaterialPtr, String> {
+        let scalar = |t: &TextureDescription| create_texture(t, false);
+        Ok(Arc::new(Self {
+            base_color: create_texture(&desc.base_color, true)?,
+            metallic: scalar(&desc.metallic)?,
+            roughness: scalar(&desc.roughness)?,
+            anisotropy: scalar(&desc.anisotropy)?,
+            specular: scalar(&desc.specular)?,
+            sheen: scalar(&desc.sheen)?,
+            sheen_tint: scalar(&desc.sheen_tint)?,
+            clearcoat: scalar(&desc.clearcoat)?,
+            clearcoat_roughness: scalar(&desc.clearcoat_roughness)?,
+            transmission: scalar(&desc.transmission)?,
+            ior: scalar(&desc.ior)?,
+        }))
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let scalar = |t: &TexturePtr, max: f64| texture_value(t, rec).x().clamp(0.0, max);
+        let roughness = scalar(&self.roughness, 1.0);
+        let ior = scalar(&self.ior, f64::INFINITY).max(1.0);
+        PrincipledLobes {
+            base_color: texture_value(&self.base_color, rec),
+            metallic: scalar(&self.metallic, 1.0),
+            specular: scalar(&self.specular, f64::INFINITY),
+            sheen: scalar(&self.sheen, f64::INFINITY),
+            sheen_tint: scalar(&self.sheen_tint, 1.0),
+            clearcoat: scalar(&self.clearcoat, 1.0),
+            transmission: scalar(&self.transmission, 1.0),
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            roughness,
+            ggx: Ggx::new(roughness, scalar(&self.anisotropy, 1.0)),
+            clearcoat_ggx: Ggx::new(scalar(&self.clearcoat_roughness, 1.0), 0.0),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        let frame = Frame::new(&rec.normal);
+        let (value, _) = self
+            .lobes(rec)
+            .eval_pdf(&frame.to_local(wi), &frame.to_local(wo));
+        value
+    }
+
+    /// one of the lobes, picked by their weights, with the density of all
+    /// of them
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&rec.normal);
+        let wo_local = frame.to_local(wo);
+        let lobes = self.lobes(rec);
+        let [pd, ps, pt, _] = lobes.probabilities();
+        let x = random();
+        let wi = if x < pd {
+            let mut d = Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector();
+            if d.is_near_zero() {
+                d = Vec3::new(0.0, 0.0, 1.0);
+            }
+            unit_vector(&d)
+        } else if x < pd + ps {
+            lobes.ggx.sample_reflection(&wo_local)?
+        } else if x < pd + ps + pt {
+            lobes.ggx.sample_dielectric(lobes.eta, &wo_local)?
+        } else {
+            lobes.clearcoat_ggx.sample_reflection(&wo_local)?
         };
+        let (value, pdf) = lobes.eval_pdf(&wi, &wo_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi: frame.to_world(&wi),
+            value,
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame = Frame::new(&rec.normal);
+        let (_, pdf) = self
+            .lobes(rec)
+            .eval_pdf(&frame.to_local(wi), &frame.to_local(wo));
+        pdf
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        texture_value(&self.base_color, rec)
+    }
+}
 
-        RayScatter::Scatter(Scattered {
-            attenuation: Color::new(1.0, 1.0, 1.0),
-            ray: Ray::new(rec.p, direction),
+/// Scattering within a participating medium, by the Henyey-Greenstein phase
+/// function rather than from a surface, so the normal is ignored and `eval`
+/// has no cosine. Light mostly keeps going for a positive asymmetry `g`,
+/// mostly comes back for a negative one, and scatters evenly for 0.
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IsotropicDescription {
+    pub albedo: [f64; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HenyeyGreensteinDescription {
+    pub albedo: [f64; 3],
+    /// mean cosine of the scattering angle, between -1 and 1
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> MaterialPtr {
+        Arc::new(Self {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
         })
     }
+
+    pub fn from(desc: &HenyeyGreensteinDescription) -> MaterialPtr {
+        Self::new(
+            Color::new(desc.albedo[0], desc.albedo[1], desc.albedo[2]),
+            desc.g,
+        )
+    }
+
+    pub fn isotropic(desc: &IsotropicDescription) -> MaterialPtr {
+        Self::new(
+            Color::new(desc.albedo[0], desc.albedo[1], desc.albedo[2]),
+            0.0,
+        )
+    }
+
+    /// density of light turning by an angle of cosine `cos_theta`
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn eval(&self, _rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        self.albedo * self.phase(-dot(wi, wo))
+    }
+
+    fn sample(&self, _rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        let g = self.g;
+        let u = random();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random();
+        // the light travelled along the opposite of `wi` before turning
+        // towards `wo`
+        let travel = Frame::new(wo).to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        let pdf = self.phase(cos_theta);
+        Some(BsdfSample {
+            wi: -travel,
+            value: self.albedo * pdf,
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        self.phase(-dot(wi, wo))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiffuseLightDescription {
+    pub emit: [f64; 3],
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> MaterialPtr {
+        Arc::new(Self { emit })
+    }
+
+    pub fn from(desc: &DiffuseLightDescription) -> MaterialPtr {
+        Self::new(Color::new(desc.emit[0], desc.emit[1], desc.emit[2]))
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::zero()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -161,12 +979,220 @@ pub enum MaterialDescription {
     Metal(MetalDescription),
     #[serde(rename = "dielectric")]
     Dielectric(DielectricDescription),
+    #[serde(rename = "diffuse_light")]
+    DiffuseLight(DiffuseLightDescription),
+    #[serde(rename = "conductor")]
+    Conductor(ConductorDescription),
+    #[serde(rename = "rough_dielectric")]
+    RoughDielectric(RoughDielectricDescription),
+    #[serde(rename = "principled")]
+    Principled(Box<PrincipledDescription>),
+    #[serde(rename = "isotropic")]
+    Isotropic(IsotropicDescription),
+    #[serde(rename = "henyey_greenstein")]
+    HenyeyGreenstein(HenyeyGreensteinDescription),
 }
 
-pub fn create_material(desc: &MaterialDescription) -> MaterialPtr {
-    match desc {
+impl MaterialDescription {
+    /// calls `f` on the path of every image the material reads
+    pub fn for_each_file(&mut self, f: &mut impl FnMut(&mut String)) {
+        if let MaterialDescription::Principled(d) = self {
+            for t in d.textures_mut() {
+                t.for_each_file(f);
+            }
+        }
+    }
+}
+
+pub fn create_material(desc: &MaterialDescription) -> Result<MaterialPtr, String> {
+    Ok(match desc {
         MaterialDescription::Lambertian(d) => Lambertian::from(d),
         MaterialDescription::Metal(d) => Metal::from(d),
         MaterialDescription::Dielectric(d) => Dielectric::from(d),
+        MaterialDescription::DiffuseLight(d) => DiffuseLight::from(d),
+        MaterialDescription::Conductor(d) => Conductor::from(d),
+        MaterialDescription::RoughDielectric(d) => RoughDielectric::from(d),
+        MaterialDescription::Principled(d) => Principled::from(d)?,
+        MaterialDescription::Isotropic(d) => HenyeyGreenstein::isotropic(d),
+        MaterialDescription::HenyeyGreenstein(d) => HenyeyGreenstein::from(d),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Conductor, Dielectric, Dispersion, HenyeyGreenstein, Lambertian, Metal, Principled,
+        RoughDielectric,
+    };
+    use crate::color::Color;
+    use crate::objects::HitRecord;
+    use crate::utils::PI;
+    use crate::vec3::{unit_vector, Point3, Vec3};
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    fn hit_record() -> HitRecord {
+        HitRecord {
+            p: Point3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            mat: Lambertian::new(Color::zero()),
+            t: 1.0,
+            distance: 1.0,
+            front_face: true,
+            object_id: 0,
+            u: 0.0,
+            v: 0.0,
+            wavelength: None,
+        }
+    }
+
+    #[test]
+    fn fuzzy_metal_pdf_integrates_to_one() {
+        let rec = hit_record();
+        let metal = Metal::new(Color::new(1.0, 1.0, 1.0), 0.5);
+        // the lobe is symmetric around the mirror direction, the normal here
+        let n = 100000;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            let wi = Vec3::new(theta.sin(), 0.0, theta.cos());
+            total += metal.pdf(&rec, &wi, &rec.normal) * 2.0 * PI * theta.sin() * PI / n as f64;
+        }
+        assert_is_close(total, 1.0);
+    }
+
+    #[test]
+    fn microfacet_pdfs_integrate_to_one() {
+        // seen from the normal, only the reflections on microfacets tilted
+        // by more than 45 degrees go under the surface
+        let rec = hit_record();
+        let conductor = Conductor::new(
+            Color::new(0.2, 0.9, 1.1),
+            Color::new(3.9, 2.4, 2.1),
+            0.4,
+            0.0,
+        );
+        let glass = RoughDielectric::new(1.5, 0.4, 0.0);
+        let n = 1000;
+        let (mut reflected, mut transmitted) = (0.0, 0.0);
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            for j in 0..n {
+                let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let wi = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let d_omega = theta.sin() * (PI / n as f64) * (2.0 * PI / n as f64);
+                reflected += conductor.pdf(&rec, &wi, &rec.normal) * d_omega;
+                transmitted += glass.pdf(&rec, &wi, &rec.normal) * d_omega;
+            }
+        }
+        let alpha2 = 0.4f64.powi(4);
+        assert!((reflected - 1.0 / (1.0 + alpha2)).abs() < 0.001);
+        assert!((transmitted - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn principled_sampling_matches_pdf() {
+        // every lobe at once, with the glass letting light under the surface
+        // and a clear coat rough enough for the grid to resolve it
+        let desc = serde_json::from_str(
+            r#"{"base_color": [0.8, 0.5, 0.2], "metallic": 0.3, "roughness": 0.4,
+                "anisotropy": 0.5, "sheen": 0.5, "clearcoat": 0.5, "clearcoat_roughness": 0.3,
+                "transmission": 0.5}"#,
+        )
+        .unwrap();
+        let principled = Principled::from(&desc).unwrap();
+        let rec = hit_record();
+        let wo = unit_vector(&Vec3::new(0.5, 0.2, 1.0));
+
+        // the density and the reflectance over the sphere
+        let n = 1000;
+        let (mut total_pdf, mut total_value) = (0.0, Color::zero());
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            for j in 0..n {
+                let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let wi = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let d_omega = theta.sin() * (PI / n as f64) * (2.0 * PI / n as f64);
+                total_pdf += principled.pdf(&rec, &wi, &wo) * d_omega;
+                total_value += principled.eval(&rec, &wi, &wo) * d_omega;
+            }
+        }
+        assert!(total_pdf <= 1.001);
+
+        // samples carry the value and density of their direction, and are
+        // distributed by it, so their weights average to the reflectance
+        let m = 200000;
+        let mut mean = Color::zero();
+        for _ in 0..m {
+            if let Some(sample) = principled.sample(&rec, &wo) {
+                let value = principled.eval(&rec, &sample.wi, &wo);
+                assert_is_close(sample.pdf, principled.pdf(&rec, &sample.wi, &wo));
+                assert!((sample.value - value).length() < 0.0001);
+                mean += sample.weight() / m as f64;
+            }
+        }
+        assert!((mean - total_value).length() < 0.01 * total_value.length());
+    }
+
+    #[test]
+    fn absorption_follows_beer_lambert() {
+        let glass = Dielectric::new(1.5, Color::new(0.5, 0.25, 1.0), 2.0, None);
+        // crossing 4 units of glass, twice the reference
+        let sigma_a = glass.absorption().unwrap();
+        assert_is_close((-sigma_a.x() * 4.0).exp(), 0.25);
+        assert_is_close((-sigma_a.y() * 4.0).exp(), 0.0625);
+        assert_is_close((-sigma_a.z() * 4.0).exp(), 1.0);
+        // clear glass holds no medium
+        assert!(Dielectric::new(1.5, Color::new(1.0, 1.0, 1.0), 1.0, None)
+            .absorption()
+            .is_none());
+    }
+
+    #[test]
+    fn henyey_greenstein_is_normalized() {
+        let rec = hit_record();
+        let smoke = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.6);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let n = 100000;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            let wi = Vec3::new(theta.sin(), 0.0, theta.cos());
+            total += smoke.pdf(&rec, &wi, &wo) * 2.0 * PI * theta.sin() * PI / n as f64;
+        }
+        assert_is_close(total, 1.0);
+        // the samples turn by an angle of mean cosine g
+        let mut mean_cos = 0.0;
+        for _ in 0..n {
+            let sample = smoke.sample(&rec, &wo).unwrap();
+            assert_is_close(sample.weight().x(), 1.0);
+            mean_cos -= sample.wi.z() / n as f64;
+        }
+        assert!((mean_cos - 0.6).abs() < 0.01);
+    }
+
+    #[test]
+    fn dispersion_of_bk7() {
+        let bk7 = Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        // the Fraunhofer lines F, d and C
+        assert_is_close(bk7.index_of_refraction(1.5168, 486.1), 1.5224);
+        assert_is_close(bk7.index_of_refraction(1.5168, 587.6), 1.5168);
+        assert_is_close(bk7.index_of_refraction(1.5168, 656.3), 1.5143);
+        let cauchy = Dispersion::Cauchy { b: 0.0042 };
+        assert_is_close(cauchy.index_of_refraction(1.5168, 587.6), 1.5168);
+        assert!(cauchy.index_of_refraction(1.5168, 486.1) > 1.52);
     }
 }
@@ -0,0 +1,512 @@
+use std::fs;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    material::{BsdfSample, HenyeyGreenstein, Material, MaterialPtr},
+    objects::{create_object, HitRecord, Hittable, Object, ObjectDescription, RayHit},
+    ray::Ray,
+    spectrum::blackbody,
+    utils::{random, INFINITY},
+    vec3::{Point3, Vec3},
+};
+
+/// Scattering of the ray within a homogeneous medium between `t1` and `t2`,
+/// at a distance sampled from its `density`, by the phase function `phase`.
+fn medium_hit(r: &Ray, t1: f64, t2: f64, density: f64, phase: &MaterialPtr) -> RayHit {
+    let distance = -(1.0 - random()).ln() / density;
+    let t = t1 + distance / r.direction().length();
+    if t >= t2 {
+        return RayHit::NoHit;
+    }
+    // media have no surface, the normal only faces the ray
+    RayHit::Hit(HitRecord::new(&r.at(t), t, r, &-r.direction(), phase))
+}
+
+/// Smoke or fog of constant density filling a convex boundary. Rays go
+/// through it until they scatter, by the phase function of its material.
+pub struct ConstantMedium {
+    boundary: Object,
+    density: f64,
+    phase: MaterialPtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConstantMediumDescription {
+    /// convex object enclosing the medium
+    pub boundary: Box<ObjectDescription>,
+    /// probability of scattering per unit distance
+    pub density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: &Object, density: f64, phase: &MaterialPtr) -> Object {
+        Arc::new(Self {
+            boundary: boundary.clone(),
+            density,
+            phase: phase.clone(),
+        })
+    }
+
+    pub fn from(desc: &ConstantMediumDescription, phase: &MaterialPtr) -> Result<Object, String> {
+        Ok(Self::new(
+            &create_object(&desc.boundary, phase)?,
+            desc.density,
+            phase,
+        ))
+    }
+
+    /// part of the ray between `t_min` and `t_max` within the boundary
+    fn interval(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        // where the whole line enters and leaves the boundary
+        let t1 = match self.boundary.hit(r, -INFINITY, INFINITY) {
+            RayHit::Hit(rec) => rec.t,
+            RayHit::NoHit => return None,
+        };
+        let t2 = match self.boundary.hit(r, t1 + 0.0001, INFINITY) {
+            RayHit::Hit(rec) => rec.t,
+            RayHit::NoHit => return None,
+        };
+        let (t1, t2) = (t1.max(t_min), t2.min(t_max));
+        (t1 < t2).then_some((t1, t2))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        match self.interval(r, t_min, t_max) {
+            Some((t1, t2)) => medium_hit(r, t1, t2, self.density, &self.phase),
+            None => RayHit::NoHit,
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.boundary.bounding_box(time0, time1)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.interval(r, t_min, t_max) {
+            Some((t1, t2)) => (-self.density * (t2 - t1) * r.direction().length()).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+/// Dense grid of values over a box, interpolated between the centers of
+/// the voxels.
+pub struct Grid {
+    resolution: [usize; 3],
+    values: Vec<f32>,
+}
+
+impl Grid {
+    /// `values` go along x first, then y, then z
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> Self {
+        Self { resolution, values }
+    }
+
+    /// Reads the raw little endian `f32` values of a grid from `file`.
+    pub fn load(file: &str, resolution: [usize; 3]) -> Result<Self, String> {
+        if resolution.contains(&0) {
+            return Err(format!(
+                "{}: the resolution {}x{}x{} of a grid must be at least 1 along each axis",
+                file, resolution[0], resolution[1], resolution[2]
+            ));
+        }
+        let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+        let count = resolution.iter().product::<usize>();
+        if bytes.len() != 4 * count {
+            return Err(format!(
+                "{}: expected {} bytes for a grid of {}x{}x{}, found {}",
+                file,
+                4 * count,
+                resolution[0],
+                resolution[1],
+                resolution[2],
+                bytes.len()
+            ));
+        }
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).max(0.0))
+            .collect();
+        Ok(Self::new(resolution, values))
+    }
+
+    fn scale(&mut self, factor: f64) {
+        for v in &mut self.values {
+            *v *= factor as f32;
+        }
+    }
+
+    /// largest value, which no interpolated one exceeds
+    fn max(&self) -> f64 {
+        self.values.iter().fold(0.0f32, |a, &b| a.max(b)) as f64
+    }
+
+    fn voxel(&self, i: [usize; 3]) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(i[2] * ny + i[1]) * nx + i[0]] as f64
+    }
+
+    /// value at the coordinates `uvw` of the box, from 0 to 1 across it
+    fn value(&self, uvw: &Vec3) -> f64 {
+        let mut i0 = [0; 3];
+        let mut i1 = [0; 3];
+        let mut f = [0.0; 3];
+        for a in 0..3 {
+            let n = self.resolution[a];
+            let x = (uvw[a] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            i0[a] = x as usize;
+            i1[a] = (i0[a] + 1).min(n - 1);
+            f[a] = x - i0[a] as f64;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut w = 1.0;
+            let mut i = i0;
+            for a in 0..3 {
+                if corner >> a & 1 == 1 {
+                    w *= f[a];
+                    i[a] = i1[a];
+                } else {
+                    w *= 1.0 - f[a];
+                }
+            }
+            if w > 0.0 {
+                value += w * self.voxel(i);
+            }
+        }
+        value
+    }
+}
+
+/// Medium glowing as a black body, its collisions emitting in the
+/// proportion of the light they absorb.
+struct EmissiveMedium {
+    phase: MaterialPtr,
+    emit: Color,
+}
+
+impl Material for EmissiveMedium {
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        self.phase.eval(rec, wi, wo)
+    }
+
+    fn sample(&self, rec: &HitRecord, wo: &Vec3) -> Option<BsdfSample> {
+        self.phase.sample(rec, wo)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        self.phase.pdf(rec, wi, wo)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        (Color::new(1.0, 1.0, 1.0) - self.phase.albedo(rec)) * self.emit
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.phase.albedo(rec)
+    }
+}
+
+/// Number of temperatures the black body radiance is tabulated at.
+const EMISSION_TABLE_SIZE: usize = 256;
+
+/// Clouds, smoke or fire from a grid of densities, such as the output of a
+/// simulation, optionally glowing by a grid of temperatures. Rays scatter
+/// by delta tracking and shadow rays are dimmed by ratio tracking, against
+/// the highest density of the grid, which keeps both unbiased.
+pub struct VolumeGrid {
+    bbox: Aabb,
+    density: Grid,
+    /// density bounding the grid everywhere
+    majorant: f64,
+    temperature: Option<Grid>,
+    /// radiance from 0 up to the highest temperature, evenly spaced
+    emission: Vec<Color>,
+    phase: MaterialPtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VolumeGridDescription {
+    /// corners of the box the grid fills
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    /// number of voxels along x, y and z
+    pub resolution: [usize; 3],
+    /// raw little endian `f32` densities, along x first, then y, then z,
+    /// relative to the json file
+    pub density: String,
+    #[serde(default = "default_scale")]
+    pub density_scale: f64,
+    /// raw temperatures in kelvin laid out as the densities, making the hot
+    /// parts glow as black bodies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<String>,
+    #[serde(default = "default_scale")]
+    pub temperature_scale: f64,
+    #[serde(default = "default_scale")]
+    pub emission_scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl VolumeGrid {
+    pub fn new(
+        bbox: Aabb,
+        density: Grid,
+        temperature: Option<Grid>,
+        emission_scale: f64,
+        phase: &MaterialPtr,
+    ) -> Object {
+        let emission = match &temperature {
+            Some(t) => {
+                let step = t.max() / (EMISSION_TABLE_SIZE - 1) as f64;
+                (0..EMISSION_TABLE_SIZE)
+                    .map(|i| blackbody(i as f64 * step) * emission_scale)
+                    .collect()
+            }
+            None => vec![],
+        };
+        Arc::new(Self {
+            bbox,
+            majorant: density.max(),
+            density,
+            temperature,
+            emission,
+            phase: phase.clone(),
+        })
+    }
+
+    pub fn from(desc: &VolumeGridDescription, phase: &MaterialPtr) -> Result<Object, String> {
+        let mut density = Grid::load(&desc.density, desc.resolution)?;
+        density.scale(desc.density_scale);
+        let temperature = match &desc.temperature {
+            Some(file) => {
+                let mut t = Grid::load(file, desc.resolution)?;
+                t.scale(desc.temperature_scale);
+                Some(t)
+            }
+            None => None,
+        };
+        Ok(Self::new(
+            Aabb::new(
+                Point3::new(desc.min[0], desc.min[1], desc.min[2]),
+                Point3::new(desc.max[0], desc.max[1], desc.max[2]),
+            ),
+            density,
+            temperature,
+            desc.emission_scale,
+            phase,
+        ))
+    }
+
+    /// coordinates of `p` within the box, from 0 to 1 across it
+    fn uvw(&self, p: &Point3) -> Vec3 {
+        let (min, max) = (self.bbox.min(), self.bbox.max());
+        Vec3::new(
+            (p.x() - min.x()) / (max.x() - min.x()),
+            (p.y() - min.y()) / (max.y() - min.y()),
+            (p.z() - min.z()) / (max.z() - min.z()),
+        )
+    }
+
+    /// black body radiance at `uvw`, interpolated in the table
+    fn emission(&self, temperature: &Grid, uvw: &Vec3) -> Color {
+        let x = temperature.value(uvw) / temperature.max() * (EMISSION_TABLE_SIZE - 1) as f64;
+        let i = (x as usize).min(EMISSION_TABLE_SIZE - 2);
+        let f = x - i as f64;
+        self.emission[i] * (1.0 - f) + self.emission[i + 1] * f
+    }
+
+    /// Distance along the ray to the next tentative collision against the
+    /// majorant, in units of its direction.
+    fn step(&self, r: &Ray) -> f64 {
+        -(1.0 - random()).ln() / (self.majorant * r.direction().length())
+    }
+}
+
+impl Hittable for VolumeGrid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        let (mut t, t_end) = match self.bbox.interval(r, t_min, t_max) {
+            Some(i) if self.majorant > 0.0 => i,
+            _ => return RayHit::NoHit,
+        };
+        loop {
+            t += self.step(r);
+            if t >= t_end {
+                return RayHit::NoHit;
+            }
+            // the rest of the tentative collisions are with null particles
+            let p = r.at(t);
+            let uvw = self.uvw(&p);
+            if self.density.value(&uvw) > random() * self.majorant {
+                let mat = match &self.temperature {
+                    Some(temperature) if temperature.max() > 0.0 => Arc::new(EmissiveMedium {
+                        phase: self.phase.clone(),
+                        emit: self.emission(temperature, &uvw),
+                    }),
+                    _ => self.phase.clone(),
+                };
+                return RayHit::Hit(HitRecord::new(&p, t, r, &-r.direction(), &mat));
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let (mut t, t_end) = match self.bbox.interval(r, t_min, t_max) {
+            Some(i) if self.majorant > 0.0 => i,
+            _ => return 1.0,
+        };
+        let mut transmittance = 1.0;
+        loop {
+            t += self.step(r);
+            if t >= t_end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density.value(&self.uvw(&r.at(t))) / self.majorant;
+            // stop following paths that carry little, without bias
+            if transmittance < 0.1 {
+                if random() < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+}
+
+/// Homogeneous medium filling the space between the objects, fading distant
+/// surfaces into fog. The medium ends before the background, so rays
+/// leaving the scene and directional lights are not dimmed by it.
+pub struct Atmosphere {
+    density: f64,
+    phase: MaterialPtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AtmosphereDescription {
+    /// probability of scattering per unit distance
+    pub density: f64,
+    /// fraction of the light scattered rather than absorbed
+    #[serde(default = "default_albedo")]
+    pub albedo: [f64; 3],
+    /// mean cosine of the scattering angle, between -1 and 1
+    #[serde(default)]
+    pub g: f64,
+}
+
+fn default_albedo() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl Atmosphere {
+    pub fn new(density: f64, albedo: Color, g: f64) -> Self {
+        Self {
+            density,
+            phase: HenyeyGreenstein::new(albedo, g),
+        }
+    }
+
+    pub fn from(desc: &AtmosphereDescription) -> Self {
+        Self::new(
+            desc.density,
+            Color::new(desc.albedo[0], desc.albedo[1], desc.albedo[2]),
+            desc.g,
+        )
+    }
+
+    /// scattering of the ray before it reaches the object hit at `t_max`
+    pub fn hit(&self, r: &Ray, t_max: f64) -> RayHit {
+        if self.density <= 0.0 || t_max == INFINITY {
+            return RayHit::NoHit;
+        }
+        medium_hit(r, 0.0, t_max, self.density, &self.phase)
+    }
+
+    /// fraction of the light crossing the medium over `distance`
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        if distance == INFINITY {
+            1.0
+        } else {
+            (-self.density * distance).exp()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConstantMedium, Grid, VolumeGrid};
+    use crate::aabb::Aabb;
+    use crate::color::Color;
+    use crate::material::HenyeyGreenstein;
+    use crate::objects::{RayHit, Sphere};
+    use crate::ray::Ray;
+    use crate::utils::INFINITY;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn constant_medium_transmittance() {
+        let phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let sphere = Sphere::new(Point3::zero(), 1.0, &phase);
+        let medium = ConstantMedium::new(&sphere, 0.5, &phase);
+        // crossing the diameter, from outside and from the center
+        let n = 100000;
+        let passed = |origin: Point3| {
+            let r = Ray::new(origin, Vec3::new(0.0, 0.0, 2.0), 0.0);
+            (0..n)
+                .filter(|_| matches!(medium.hit(&r, 0.001, INFINITY), RayHit::NoHit))
+                .count() as f64
+                / n as f64
+        };
+        assert!((passed(Point3::new(0.0, 0.0, -3.0)) - (-1.0f64).exp()).abs() < 0.01);
+        assert!((passed(Point3::zero()) - (-0.5f64).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn volume_grid_tracking() {
+        // densities from 0 to 1 along z, averaging 0.5 over the box
+        let phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let values = (0..4 * 4 * 2).map(|i| (i / 16) as f32).collect();
+        let grid = VolumeGrid::new(
+            Aabb::new(Point3::new(-1.0, -1.0, 0.0), Point3::new(1.0, 1.0, 2.0)),
+            Grid::new([4, 4, 2], values),
+            None,
+            1.0,
+            &phase,
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let n = 100000;
+        let passed = (0..n)
+            .filter(|_| matches!(grid.hit(&r, 0.001, INFINITY), RayHit::NoHit))
+            .count() as f64
+            / n as f64;
+        let transmittance = (0..n)
+            .map(|_| grid.transmittance(&r, 0.001, INFINITY))
+            .sum::<f64>()
+            / n as f64;
+        assert!((passed - (-1.0f64).exp()).abs() < 0.01);
+        assert!((transmittance - (-1.0f64).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn empty_grids_are_rejected() {
+        let file = std::env::temp_dir().join("empty_grid.raw");
+        std::fs::write(&file, []).unwrap();
+        let file = file.to_str().unwrap();
+        for resolution in [[0, 4, 4], [4, 0, 4], [0, 0, 0]] {
+            let error = Grid::load(file, resolution).err().unwrap();
+            assert!(error.contains("at least 1"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{
+    utils::{random, PI},
+    vec3::{cross, dot, reflect, unit_vector, Vec3},
+};
+
+/// Orthonormal frame around a shading normal, the local z axis. The tangent
+/// turns around the y axis of the world, which orients anisotropic
+/// materials.
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: &Vec3) -> Self {
+        let mut s = cross(&Vec3::new(0.0, 1.0, 0.0), n);
+        if s.length_squared() < 1e-8 {
+            s = Vec3::new(1.0, 0.0, 0.0);
+        }
+        let s = unit_vector(&s);
+        Self {
+            s,
+            t: cross(n, &s),
+            n: *n,
+        }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(dot(v, &self.s), dot(v, &self.t), dot(v, &self.n))
+    }
+
+    pub fn to_world(&self, v: &Vec3) -> Vec3 {
+        self.s * v.x() + self.t * v.y() + self.n * v.z()
+    }
+}
+
+/// Trowbridge-Reitz (GGX) distribution of microfacet normals, with Smith
+/// shadowing. Directions are in the local frame of the surface.
+pub struct Ggx {
+    ax: f64,
+    ay: f64,
+}
+
+impl Ggx {
+    /// `roughness` is perceptual, its square being the width of the
+    /// distribution, which `anisotropy` stretches along the tangent
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self {
+            ax: (alpha / aspect).max(1e-3),
+            ay: (alpha * aspect).max(1e-3),
+        }
+    }
+
+    /// density of microfacet normals `m` per projected area
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let e = (m.x() / self.ax).powi(2) + (m.y() / self.ay).powi(2) + m.z().powi(2);
+        1.0 / (PI * self.ax * self.ay * e * e)
+    }
+
+    fn lambda(&self, v: &Vec3) -> f64 {
+        let a2 = (self.ax * v.x()).powi(2) + (self.ay * v.y()).powi(2);
+        ((1.0 + a2 / (v.z() * v.z())).sqrt() - 1.0) / 2.0
+    }
+
+    /// masking of the microfacets seen from `v`
+    pub fn g1(&self, v: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(v))
+    }
+
+    /// height correlated masking and shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Microfacet normal visible from `wo`, picked in proportion to its
+    /// projected area (Heitz 2018).
+    pub fn sample_visible(&self, wo: &Vec3) -> Vec3 {
+        let vh = unit_vector(&Vec3::new(self.ax * wo.x(), self.ay * wo.y(), wo.z()));
+        let len_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len_squared > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(&vh, &t1);
+        let r = random().sqrt();
+        let phi = 2.0 * PI * random();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        unit_vector(&Vec3::new(
+            self.ax * nh.x(),
+            self.ay * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+
+    /// density of `sample_visible` picking `m`
+    pub fn pdf_visible(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        self.g1(wo) * dot(wo, m).max(0.0) * self.d(m) / wo.z()
+    }
+
+    /// Reflection from `wi` towards `wo` without the Fresnel factor: the
+    /// BRDF times the cosine of `wi`, the density of `sample_reflection`
+    /// and the cosine between `wo` and the microfacet normal. `None` if
+    /// either direction is under the surface.
+    pub fn reflection(&self, wi: &Vec3, wo: &Vec3) -> Option<(f64, f64, f64)> {
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return None;
+        }
+        let m = unit_vector(&(*wi + *wo));
+        let cos = dot(wo, &m);
+        let value = self.d(&m) * self.g(wo, wi) / (4.0 * wo.z());
+        Some((value, self.pdf_visible(wo, &m) / (4.0 * cos), cos))
+    }
+
+    /// reflection of `wo` on a visible microfacet
+    pub fn sample_reflection(&self, wo: &Vec3) -> Option<Vec3> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = reflect(&-*wo, &self.sample_visible(wo));
+        (wi.z() > 0.0).then_some(wi)
+    }
+
+    /// Reflection and transmission from `wi` towards `wo` through an
+    /// interface with the ratio `eta` of the index of refraction across
+    /// the surface to the one on the side of `wo` (Walter et al. 2007):
+    /// the BSDF times the cosine of `wi` and the density of
+    /// `sample_dielectric`. The radiance is not scaled by the squared
+    /// ratio of the indices on transmission, as with `Dielectric`, which
+    /// cancels out through closed objects.
+    pub fn dielectric(&self, eta: f64, wi: &Vec3, wo: &Vec3) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (0.0, 0.0);
+        }
+        let reflection = wi.z() > 0.0;
+        let m = if reflection {
+            unit_vector(&(*wi + *wo))
+        } else {
+            unit_vector(&(*wi * eta + *wo))
+        };
+        let m = if m.z() < 0.0 { -m } else { m };
+        // microfacets facing away from either direction
+        if dot(&m, wi) * wi.z() <= 0.0 || dot(&m, wo) <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let f = fresnel_dielectric(dot(wo, &m), eta);
+        let d = self.d(&m);
+        let g = self.g(wo, wi);
+        let pdf_m = self.pdf_visible(wo, &m);
+        if reflection {
+            let value = f * d * g / (4.0 * wo.z());
+            (value, f * pdf_m / (4.0 * dot(wo, &m)))
+        } else {
+            let denom = (dot(wi, &m) + dot(wo, &m) / eta).powi(2);
+            let value = (1.0 - f) * d * g * (dot(wi, &m) * dot(wo, &m)).abs() / (wo.z() * denom);
+            (value, (1.0 - f) * pdf_m * dot(wi, &m).abs() / denom)
+        }
+    }
+
+    /// reflection or refraction of `wo` on a visible microfacet, picked by
+    /// its Fresnel reflectance
+    pub fn sample_dielectric(&self, eta: f64, wo: &Vec3) -> Option<Vec3> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let m = self.sample_visible(wo);
+        if fresnel_dielectric(dot(wo, &m), eta) > random() {
+            Some(reflect(&-*wo, &m))
+        } else {
+            refract(wo, &m, eta)
+        }
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, from its value `f0`
+/// at normal incidence.
+pub fn schlick(cos_i: f64, f0: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos_i).clamp(0.0, 1.0).powi(5)
+}
+
+/// Fresnel reflectance of unpolarized light arriving at the cosine `cos_i`
+/// on an interface with the relative index of refraction `eta`.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Fresnel reflectance of a conductor of complex index of refraction
+/// `eta + i k`.
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t1 = a2b2 + cos2;
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    (rs + rp) / 2.0
+}
+
+/// Direction refracted from `wo` through the microfacet `m`, both pointing
+/// away from the surface, for the ratio `eta` of the index of refraction
+/// across the surface to the one on the side of `wo`. `None` on total
+/// internal reflection.
+pub fn refract(wo: &Vec3, m: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = dot(wo, m);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + *m * (cos_i / eta - cos_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fresnel_conductor, fresnel_dielectric, Ggx};
+    use crate::utils::PI;
+    use crate::vec3::Vec3;
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn ggx_projected_area_is_one() {
+        let ggx = Ggx::new(0.5, 0.6);
+        let n = 1000;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+            for j in 0..n {
+                let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let m = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let d_omega = theta.sin() * (PI / 2.0 / n as f64) * (2.0 * PI / n as f64);
+                total += ggx.d(&m) * m.z() * d_omega;
+            }
+        }
+        assert_is_close(total, 1.0);
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        assert_is_close(fresnel_dielectric(1.0, 1.5), 0.04);
+        // a dielectric is a conductor without absorption
+        assert_is_close(
+            fresnel_conductor(0.6, 1.5, 0.0),
+            fresnel_dielectric(0.6, 1.5),
+        );
+        assert_is_close(
+            fresnel_conductor(1.0, 0.2, 3.0),
+            (0.64 + 9.0) / (1.44 + 9.0),
+        );
+    }
+}
@@ -1,17 +1,32 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
 use crate::material::MaterialPtr;
+use crate::medium::{ConstantMedium, ConstantMediumDescription, VolumeGrid, VolumeGridDescription};
 use crate::ray::Ray;
-use crate::vec3::{dot, Point3, Vec3};
+use crate::utils::{deg_to_rad, random, INFINITY, PI};
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
 
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub mat: MaterialPtr,
     pub t: f64,
+    /// distance travelled along the ray, which `t` measures in units of its
+    /// direction
+    pub distance: f64,
     pub front_face: bool,
+    /// id of the object hit, 0 if it has none
+    pub object_id: u32,
+    /// texture coordinates of the surface
+    pub u: f64,
+    pub v: f64,
+    /// hero wavelength of the path in nanometers, in spectral mode
+    pub wavelength: Option<f64>,
 }
 
 pub enum RayHit {
@@ -31,13 +46,49 @@ impl HitRecord {
             },
             mat: mat.clone(),
             t,
+            distance: t * r.direction().length(),
             front_face,
+            object_id: 0,
+            u: 0.0,
+            v: 0.0,
+            wavelength: None,
         }
     }
 }
 
 pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit;
+
+    /// box enclosing the object for the whole interval between `time0` and
+    /// `time1`, if the object is bounded
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// fraction of the light crossing the object between `t_min` and
+    /// `t_max` along the ray, 0 if a surface stands in between
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.hit(r, t_min, t_max) {
+            RayHit::Hit(_) => 0.0,
+            RayHit::NoHit => 1.0,
+        }
+    }
+
+    /// whether `random` can pick directions towards the object, so that it
+    /// can be sampled as a light
+    fn is_sampleable(&self) -> bool {
+        false
+    }
+
+    /// density over solid angle of `random` choosing `direction` from
+    /// `origin`, 0 for objects that cannot be sampled as lights
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+
+    /// random direction from `origin` towards the object, if it can be
+    /// sampled
+    fn random(&self, _origin: &Point3, _time: f64) -> Option<Vec3> {
+        None
+    }
 }
 
 pub type Object = Arc<dyn Hittable>;
@@ -50,8 +101,8 @@ pub struct Sphere {
 
 #[derive(Serialize, Deserialize)]
 pub struct SphereDescription {
-    center: [f64; 3],
-    radius: f64,
+    pub center: [f64; 3],
+    pub radius: f64,
 }
 
 impl Sphere {
@@ -72,53 +123,689 @@ impl Sphere {
     }
 }
 
+/// nearest distance along the ray at which it hits the sphere
+fn sphere_root(center: &Point3, radius: f64, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+    let oc = r.origin() - *center;
+    let a = r.direction().length_squared();
+    let half_b = dot(&oc, &r.direction());
+    let c = oc.length_squared() - radius * radius;
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let mut root = (-half_b - sqrtd) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
+        if root < t_min || t_max < root {
+            return None;
+        }
+    }
+    Some(root)
+}
+
+fn hit_sphere(
+    center: &Point3,
+    radius: f64,
+    mat: &MaterialPtr,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> RayHit {
+    match sphere_root(center, radius, r, t_min, t_max) {
+        Some(root) => {
+            let intersection = r.at(root);
+            let outward_normal = (intersection - *center) / radius;
+            let mut rec = HitRecord::new(&intersection, root, r, &outward_normal, mat);
+            // longitude around the y axis from -x, and latitude from -y
+            let theta = (-outward_normal.y()).clamp(-1.0, 1.0).acos();
+            let phi = (-outward_normal.z()).atan2(outward_normal.x()) + PI;
+            rec.u = phi / (2.0 * PI);
+            rec.v = theta / PI;
+            RayHit::Hit(rec)
+        }
+        None => RayHit::NoHit,
+    }
+}
+
+fn sphere_box(center: &Point3, radius: f64) -> Aabb {
+    let rvec = Vec3::new(radius.abs(), radius.abs(), radius.abs());
+    Aabb::new(*center - rvec, *center + rvec)
+}
+
+/// density of directions sampled uniformly within the cone the sphere
+/// subtends from `origin`, or over all directions from inside of it
+fn sphere_pdf_value(center: &Point3, radius: f64, origin: &Point3, direction: &Vec3) -> f64 {
+    let r = Ray::new(*origin, *direction, 0.0);
+    if sphere_root(center, radius, &r, 0.001, INFINITY).is_none() {
+        return 0.0;
+    }
+    let distance_squared = (*center - *origin).length_squared();
+    if distance_squared <= radius * radius {
+        return 1.0 / (4.0 * PI);
+    }
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+fn sphere_random(center: &Point3, radius: f64, origin: &Point3) -> Vec3 {
+    let direction = *center - *origin;
+    let distance_squared = direction.length_squared();
+    if distance_squared <= radius * radius {
+        return Vec3::random_unit_vector();
+    }
+
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    Vec3::random_in_cone(&unit_vector(&direction), cos_theta_max)
+}
+
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
-        let oc = r.origin() - self.center;
-        let a = r.direction().length_squared();
-        let half_b = dot(&oc, &r.direction());
-        let c = oc.length_squared() - self.radius * self.radius;
+        hit_sphere(&self.center, self.radius, &self.mat, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _: f64, _: f64) -> Option<Aabb> {
+        Some(sphere_box(&self.center, self.radius))
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+        sphere_pdf_value(&self.center, self.radius, origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Option<Vec3> {
+        Some(sphere_random(&self.center, self.radius, origin))
+    }
+}
+
+/// Sphere moving linearly from `center0` at `time0` to `center1` at `time1`.
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    mat: MaterialPtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MovingSphereDescription {
+    pub center0: [f64; 3],
+    pub center1: [f64; 3],
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+}
 
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        mat: &MaterialPtr,
+    ) -> Object {
+        Arc::new(Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            mat: mat.clone(),
+        })
+    }
+
+    pub fn from(desc: &MovingSphereDescription, mat: &MaterialPtr) -> Object {
+        Self::new(
+            Point3::new(desc.center0[0], desc.center0[1], desc.center0[2]),
+            Point3::new(desc.center1[0], desc.center1[1], desc.center1[2]),
+            desc.time0,
+            desc.time1,
+            desc.radius,
+            mat,
+        )
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        self.center0
+            + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        hit_sphere(
+            &self.center(r.time()),
+            self.radius,
+            &self.mat,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let box0 = sphere_box(&self.center(time0), self.radius);
+        let box1 = sphere_box(&self.center(time1), self.radius);
+        Some(box0.surrounding(&box1))
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        sphere_pdf_value(&self.center(time), self.radius, origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Option<Vec3> {
+        Some(sphere_random(&self.center(time), self.radius, origin))
+    }
+}
+
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+    mat: MaterialPtr,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QuadDescription {
+    pub q: [f64; 3],
+    pub u: [f64; 3],
+    pub v: [f64; 3],
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: &MaterialPtr) -> Object {
+        let n = cross(&u, &v);
+        let normal = unit_vector(&n);
+        Arc::new(Self {
+            q,
+            u,
+            v,
+            w: n / dot(&n, &n),
+            normal,
+            d: dot(&normal, &q),
+            area: n.length(),
+            mat: mat.clone(),
+        })
+    }
+
+    pub fn from(desc: &QuadDescription, mat: &MaterialPtr) -> Object {
+        Self::new(
+            Point3::new(desc.q[0], desc.q[1], desc.q[2]),
+            Vec3::new(desc.u[0], desc.u[1], desc.u[2]),
+            Vec3::new(desc.v[0], desc.v[1], desc.v[2]),
+            mat,
+        )
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        let denom = dot(&self.normal, &r.direction());
+
+        // ray parallel to the plane
+        if denom.abs() < 1e-8 {
             return RayHit::NoHit;
         }
 
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return RayHit::NoHit;
+        let t = (self.d - dot(&self.normal, &r.origin())) / denom;
+        if t < t_min || t_max < t {
+            return RayHit::NoHit;
+        }
+
+        // planar coordinates of the intersection
+        let intersection = r.at(t);
+        let planar = intersection - self.q;
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return RayHit::NoHit;
+        }
+
+        let mut rec = HitRecord::new(&intersection, t, r, &self.normal, &self.mat);
+        rec.u = alpha;
+        rec.v = beta;
+        RayHit::Hit(rec)
+    }
+
+    fn bounding_box(&self, _: f64, _: f64) -> Option<Aabb> {
+        let diag0 = Aabb::new(self.q, self.q + self.u + self.v);
+        let diag1 = Aabb::new(self.q + self.u, self.q + self.v);
+        Some(diag0.surrounding(&diag1).padded(1e-4))
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let rec = match self.hit(&Ray::new(*origin, *direction, time), 0.001, INFINITY) {
+            RayHit::Hit(rec) => rec,
+            RayHit::NoHit => return 0.0,
+        };
+        // area density converted to solid angle
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = dot(direction, &self.normal).abs() / direction.length();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Option<Vec3> {
+        Some(self.q + self.u * random() + self.v * random() - *origin)
+    }
+}
+
+/// Keyframe of the transform of an object. Rotations and scales are about
+/// the center of the object, and applied before the translation.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KeyframeDescription {
+    pub time: f64,
+    #[serde(default)]
+    pub translate: [f64; 3],
+    /// angles in degrees about the x, y and z axes, applied in this order
+    #[serde(default)]
+    pub rotate: [f64; 3],
+    /// factors along the x, y and z axes
+    #[serde(default = "default_scale")]
+    pub scale: [f64; 3],
+}
+
+fn default_scale() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+/// `v` rotated by the angles in radians about the x, y and z axes, or back
+/// if `inverse`.
+fn rotate(v: &Vec3, angles: &Vec3, inverse: bool) -> Vec3 {
+    let about = |v: Vec3, axis: usize| {
+        let angle = if inverse { -angles[axis] } else { angles[axis] };
+        let (s, c) = angle.sin_cos();
+        let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut r = v;
+        r[i] = c * v[i] - s * v[j];
+        r[j] = s * v[i] + c * v[j];
+        r
+    };
+    if inverse {
+        about(about(about(*v, 2), 1), 0)
+    } else {
+        about(about(about(*v, 0), 1), 2)
+    }
+}
+
+/// Translation, rotation and scale of an object at some time.
+#[derive(Clone, Copy)]
+struct Transform {
+    translate: Vec3,
+    /// angles in radians
+    rotate: Vec3,
+    scale: Vec3,
+}
+
+impl Transform {
+    fn from(k: &KeyframeDescription) -> Self {
+        let v = |a: [f64; 3]| Vec3::new(a[0], a[1], a[2]);
+        Self {
+            translate: v(k.translate),
+            rotate: v(k.rotate.map(deg_to_rad)),
+            scale: v(k.scale),
+        }
+    }
+
+    fn lerp(&self, other: &Transform, f: f64) -> Self {
+        Self {
+            translate: self.translate + (other.translate - self.translate) * f,
+            rotate: self.rotate + (other.rotate - self.rotate) * f,
+            scale: self.scale + (other.scale - self.scale) * f,
+        }
+    }
+
+    fn vector_to_world(&self, v: &Vec3) -> Vec3 {
+        rotate(&(*v * self.scale), &self.rotate, false)
+    }
+
+    fn vector_to_object(&self, v: &Vec3) -> Vec3 {
+        let v = rotate(v, &self.rotate, true);
+        Vec3::new(
+            v.x() / self.scale.x(),
+            v.y() / self.scale.y(),
+            v.z() / self.scale.z(),
+        )
+    }
+
+    /// normals take the inverse scale, to stay perpendicular to the surface
+    fn normal_to_world(&self, n: &Vec3) -> Vec3 {
+        let n = Vec3::new(
+            n.x() / self.scale.x(),
+            n.y() / self.scale.y(),
+            n.z() / self.scale.z(),
+        );
+        unit_vector(&rotate(&n, &self.rotate, false))
+    }
+}
+
+/// Object transformed over time about `pivot`, interpolating linearly
+/// between keyframes.
+pub struct Animated {
+    object: Object,
+    pivot: Point3,
+    keyframes: Vec<(f64, Transform)>,
+}
+
+impl Animated {
+    pub fn new(object: &Object, keyframes: &[KeyframeDescription]) -> Object {
+        let mut keyframes: Vec<(f64, Transform)> = keyframes
+            .iter()
+            .map(|k| (k.time, Transform::from(k)))
+            .collect();
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let pivot = object
+            .bounding_box(0.0, 0.0)
+            .map_or(Point3::zero(), |b| b.center());
+        Arc::new(Self {
+            object: object.clone(),
+            pivot,
+            keyframes,
+        })
+    }
+
+    fn transform(&self, time: f64) -> Transform {
+        let i = self.keyframes.partition_point(|k| k.0 <= time);
+        if i == 0 {
+            return self.keyframes.first().map_or(
+                Transform {
+                    translate: Vec3::zero(),
+                    rotate: Vec3::zero(),
+                    scale: Vec3::new(1.0, 1.0, 1.0),
+                },
+                |k| k.1,
+            );
+        }
+        if i == self.keyframes.len() {
+            return self.keyframes[i - 1].1;
+        }
+        let (t0, k0) = self.keyframes[i - 1];
+        let (t1, k1) = self.keyframes[i];
+        k0.lerp(&k1, (time - t0) / (t1 - t0))
+    }
+
+    fn point_to_world(&self, transform: &Transform, p: &Point3) -> Point3 {
+        self.pivot + transform.translate + transform.vector_to_world(&(*p - self.pivot))
+    }
+
+    fn point_to_object(&self, transform: &Transform, p: &Point3) -> Point3 {
+        self.pivot + transform.vector_to_object(&(*p - self.pivot - transform.translate))
+    }
+
+    /// the ray in the space of the object, where its parameter is unchanged
+    fn ray_to_object(&self, transform: &Transform, r: &Ray) -> Ray {
+        Ray::new(
+            self.point_to_object(transform, &r.origin()),
+            transform.vector_to_object(&r.direction()),
+            r.time(),
+        )
+    }
+
+    /// box of the object between two times with no keyframe in between
+    fn segment_box(&self, b: &Aabb, t0: &Transform, t1: &Transform) -> Aabb {
+        let corners: Vec<Point3> = (0..8)
+            .map(|i| {
+                let pick = |axis: usize| {
+                    if i & (1 << axis) == 0 {
+                        b.min()[axis]
+                    } else {
+                        b.max()[axis]
+                    }
+                };
+                Point3::new(pick(0), pick(1), pick(2))
+            })
+            .collect();
+        if (t1.rotate - t0.rotate).length_squared() == 0.0 {
+            // the corners move linearly, so their ends enclose the motion
+            let points = corners
+                .iter()
+                .flat_map(|c| [self.point_to_world(t0, c), self.point_to_world(t1, c)]);
+            return points
+                .map(|p| Aabb::new(p, p))
+                .reduce(|a, b| a.surrounding(&b))
+                .unwrap();
+        }
+        // a rotating object stays within the largest sphere around the pivot
+        let radius = corners
+            .iter()
+            .map(|c| (*c - self.pivot).length())
+            .fold(0.0, f64::max);
+        let scale = [t0.scale, t1.scale]
+            .iter()
+            .flat_map(|s| [s.x().abs(), s.y().abs(), s.z().abs()])
+            .fold(0.0, f64::max);
+        let r = Vec3::new(1.0, 1.0, 1.0) * (radius * scale);
+        let (c0, c1) = (self.pivot + t0.translate, self.pivot + t1.translate);
+        Aabb::new(c0 - r, c0 + r).surrounding(&Aabb::new(c1 - r, c1 + r))
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        let transform = self.transform(r.time());
+        let local = self.ray_to_object(&transform, r);
+        match self.object.hit(&local, t_min, t_max) {
+            RayHit::Hit(mut rec) => {
+                rec.p = self.point_to_world(&transform, &rec.p);
+                rec.normal = transform.normal_to_world(&rec.normal);
+                rec.distance = rec.t * r.direction().length();
+                RayHit::Hit(rec)
             }
+            RayHit::NoHit => RayHit::NoHit,
         }
-        let intersection = r.at(root);
-        let outward_normal = (intersection - self.center) / self.radius;
-        RayHit::Hit(HitRecord::new(
-            &intersection,
-            root,
-            r,
-            &outward_normal,
-            &self.mat,
-        ))
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let b = self.object.bounding_box(time0, time1)?;
+
+        // the transform is interpolated linearly between the keyframes, so
+        // the interval splits at the keyframes within it
+        let mut times = vec![time0];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.0)
+                .filter(|&t| time0 < t && t < time1),
+        );
+        times.push(time1);
+        times
+            .windows(2)
+            .map(|w| self.segment_box(&b, &self.transform(w[0]), &self.transform(w[1])))
+            .reduce(|a, b| a.surrounding(&b))
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let local = self.ray_to_object(&self.transform(r.time()), r);
+        self.object.transmittance(&local, t_min, t_max)
+    }
+
+    /// solid angles only keep their size under rotations and uniform scales
+    fn is_sampleable(&self) -> bool {
+        self.object.is_sampleable()
+            && self
+                .keyframes
+                .iter()
+                .all(|(_, k)| k.scale.x() == k.scale.y() && k.scale.y() == k.scale.z())
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let transform = self.transform(time);
+        self.object.pdf_value(
+            &self.point_to_object(&transform, origin),
+            &transform.vector_to_object(direction),
+            time,
+        )
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Option<Vec3> {
+        let transform = self.transform(time);
+        self.object
+            .random(&self.point_to_object(&transform, origin), time)
+            .map(|d| transform.vector_to_world(&d))
+    }
+}
+
+/// Gives an id to the hits of an object, for the object id output pass.
+pub struct Identified {
+    object: Object,
+    id: u32,
+}
+
+impl Identified {
+    pub fn new(object: &Object, id: u32) -> Object {
+        Arc::new(Self {
+            object: object.clone(),
+            id,
+        })
+    }
+}
+
+impl Hittable for Identified {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        match self.object.hit(r, t_min, t_max) {
+            RayHit::Hit(mut rec) => {
+                rec.object_id = self.id;
+                RayHit::Hit(rec)
+            }
+            RayHit::NoHit => RayHit::NoHit,
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.object.transmittance(r, t_min, t_max)
+    }
+
+    fn is_sampleable(&self) -> bool {
+        self.object.is_sampleable()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.object.pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Option<Vec3> {
+        self.object.random(origin, time)
     }
 }
 
 pub struct World {
     objects: Vec<Object>,
+    /// objects that can be referred to by name, e.g. to focus on them
+    named: BTreeMap<String, Object>,
+    /// emissive objects, sampled for direct lighting
+    lights: Vec<Object>,
 }
 
 impl World {
     pub fn new() -> Self {
-        Self { objects: vec![] }
+        Self {
+            objects: vec![],
+            named: BTreeMap::new(),
+            lights: vec![],
+        }
     }
 
     pub fn add(&mut self, obj: &Object) {
         self.objects.push(obj.clone());
     }
 
+    pub fn add_named(&mut self, name: &str, obj: &Object) -> Result<(), String> {
+        if self.named.contains_key(name) {
+            return Err(format!("object '{}' defined more than once", name));
+        }
+        self.named.insert(name.to_owned(), obj.clone());
+        self.add(obj);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.named.get(name)
+    }
+
+    /// Marks an object of the world as a light, which it must be able to
+    /// sample.
+    pub fn add_light(&mut self, obj: &Object) {
+        self.lights.push(obj.clone());
+    }
+
+    pub fn lights(&self) -> &[Object] {
+        &self.lights
+    }
+
+    /// Closest hit on one of the lights, passing through everything else.
+    pub fn hit_light(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
+        let mut closest = RayHit::NoHit;
+        let mut closest_so_far = t_max;
+        for light in &self.lights {
+            if let RayHit::Hit(rec) = light.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                closest = RayHit::Hit(rec);
+            }
+        }
+        closest
+    }
+
+    /// density of `random_light_direction` choosing `direction`
+    pub fn light_pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|l| l.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.lights.len().max(1) as f64
+    }
+
+    /// direction from `origin` towards one of the lights picked at random
+    pub fn random_light_direction(&self, origin: &Point3, time: f64) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let i = ((random() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        self.lights[i].random(origin, time)
+    }
+
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.named.clear();
+        self.lights.clear();
+    }
+
+    /// Groups the objects bounded between `time0` and `time1` into a
+    /// bounding volume hierarchy, leaving the unbounded ones as they are.
+    pub fn build_bvh(&mut self, time0: f64, time1: f64) {
+        let (bounded, mut unbounded): (Vec<Object>, Vec<Object>) = self
+            .objects
+            .drain(..)
+            .partition(|o| o.bounding_box(time0, time1).is_some());
+        if let Some(bvh) = BvhNode::new(&bounded, time0, time1) {
+            unbounded.push(bvh);
+        }
+        self.objects = unbounded;
     }
 
     fn hit_recursive(&self, r: &Ray, t_min: f64, t_max: f64, i: usize) -> RayHit {
@@ -143,6 +830,29 @@ impl Hittable for World {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> RayHit {
         self.hit_recursive(r, t_min, t_max, 0)
     }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        for obj in &self.objects {
+            transmittance *= obj.transmittance(r, t_min, t_max);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|o| o.bounding_box(time0, time1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.surrounding(&b?)))
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -150,10 +860,177 @@ impl Hittable for World {
 pub enum ObjectDescription {
     #[serde(rename = "sphere")]
     Sphere(SphereDescription),
+    #[serde(rename = "moving_sphere")]
+    MovingSphere(MovingSphereDescription),
+    #[serde(rename = "quad")]
+    Quad(QuadDescription),
+    #[serde(rename = "constant_medium")]
+    ConstantMedium(ConstantMediumDescription),
+    #[serde(rename = "volume_grid")]
+    VolumeGrid(VolumeGridDescription),
 }
 
-pub fn create_object(desc: &ObjectDescription, mat: &MaterialPtr) -> Object {
-    match desc {
+impl ObjectDescription {
+    /// calls `f` on the path of every file the object reads
+    pub fn for_each_file(&mut self, f: &mut impl FnMut(&mut String)) {
+        match self {
+            ObjectDescription::ConstantMedium(d) => d.boundary.for_each_file(f),
+            ObjectDescription::VolumeGrid(d) => {
+                f(&mut d.density);
+                if let Some(file) = &mut d.temperature {
+                    f(file);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn create_object(desc: &ObjectDescription, mat: &MaterialPtr) -> Result<Object, String> {
+    Ok(match desc {
         ObjectDescription::Sphere(d) => Sphere::from(d, mat),
+        ObjectDescription::MovingSphere(d) => MovingSphere::from(d, mat),
+        ObjectDescription::Quad(d) => Quad::from(d, mat),
+        ObjectDescription::ConstantMedium(d) => ConstantMedium::from(d, mat)?,
+        ObjectDescription::VolumeGrid(d) => VolumeGrid::from(d, mat)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Animated, Hittable, KeyframeDescription, Quad, RayHit, Sphere};
+    use crate::material::{Lambertian, MaterialPtr};
+    use crate::ray::Ray;
+    use crate::utils::{INFINITY, PI};
+    use crate::vec3::{unit_vector, Point3, Vec3};
+
+    fn assert_is_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 0.0001);
+    }
+
+    /// estimate of the solid angle of an object seen from `origin`, the
+    /// expected inverse density of its sampled directions
+    fn solid_angle(object: &Arc<dyn Hittable>, origin: &Point3) -> f64 {
+        let n = 1000;
+        (0..n)
+            .map(|_| 1.0 / object.pdf_value(origin, &object.random(origin, 0.0).unwrap(), 0.0))
+            .sum::<f64>()
+            / n as f64
+    }
+
+    #[test]
+    fn light_sampling() {
+        let mat: MaterialPtr = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let origin = Point3::zero();
+
+        // cone of half angle 30 degrees
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0, &mat);
+        let cone = 2.0 * PI * (1.0 - (PI / 6.0).cos());
+        assert_is_close(solid_angle(&sphere, &origin), cone);
+
+        // unit square far away, facing the origin
+        let quad = Quad::new(
+            Point3::new(-0.5, -0.5, -100.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &mat,
+        );
+        assert!((solid_angle(&quad, &origin) / 1e-4 - 1.0).abs() < 0.001);
+    }
+
+    fn keyframe(time: f64, rotate: [f64; 3], scale: [f64; 3]) -> KeyframeDescription {
+        KeyframeDescription {
+            time,
+            translate: [0.0, 0.0, 0.0],
+            rotate,
+            scale,
+        }
+    }
+
+    #[test]
+    fn animated_rotation_and_scale() {
+        let mat: MaterialPtr = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+
+        // a square facing z, turned to face x and stretched along z
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            &mat,
+        );
+        let turned = Animated::new(&quad, &[keyframe(0.0, [0.0, 90.0, 0.0], [2.0, 1.0, 1.0])]);
+        let hit = |z: f64| {
+            let r = Ray::new(Point3::new(5.0, 0.0, z), Vec3::new(-2.0, 0.0, 0.0), 0.0);
+            turned.hit(&r, 0.001, INFINITY)
+        };
+        match hit(1.5) {
+            RayHit::Hit(rec) => {
+                assert_is_close(rec.p.x(), 0.0);
+                assert_is_close(rec.normal.x(), 1.0);
+                assert_is_close(rec.distance, 5.0);
+            }
+            RayHit::NoHit => panic!("the stretched square must be hit"),
+        }
+        assert!(matches!(hit(2.5), RayHit::NoHit));
+
+        // normals of an ellipsoid, twice as wide along x
+        let sphere = Sphere::new(Point3::zero(), 1.0, &mat);
+        let ellipsoid = Animated::new(&sphere, &[keyframe(0.0, [0.0; 3], [2.0, 1.0, 1.0])]);
+        let x = 2.0f64.sqrt();
+        let r = Ray::new(Point3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        match ellipsoid.hit(&r, 0.001, INFINITY) {
+            RayHit::Hit(rec) => {
+                let expected = unit_vector(&Vec3::new(x / 4.0, 0.5f64.sqrt(), 0.0));
+                assert_is_close(rec.p.y(), 0.5f64.sqrt());
+                assert_is_close(rec.normal.x(), expected.x());
+                assert_is_close(rec.normal.y(), expected.y());
+            }
+            RayHit::NoHit => panic!("the ellipsoid must be hit"),
+        }
+        assert!(!ellipsoid.is_sampleable());
+    }
+
+    #[test]
+    fn animated_bounding_box() {
+        // a bar turning a quarter about z over the interval
+        let mat: MaterialPtr = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let bar = Quad::new(
+            Point3::new(-2.0, -0.1, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.2, 0.0),
+            &mat,
+        );
+        let turning = Animated::new(
+            &bar,
+            &[
+                keyframe(0.0, [0.0; 3], [1.0; 3]),
+                keyframe(1.0, [0.0, 0.0, 90.0], [1.0; 3]),
+            ],
+        );
+        let b = turning.bounding_box(0.0, 1.0).unwrap();
+        for i in 0..=10 {
+            let angle = i as f64 / 10.0 * PI / 2.0;
+            let end = Point3::new(2.0 * angle.cos(), 2.0 * angle.sin(), 0.0);
+            for axis in 0..3 {
+                assert!(b.min()[axis] <= end[axis] && end[axis] <= b.max()[axis]);
+            }
+        }
+    }
+
+    #[test]
+    fn animated_light_sampling() {
+        // the far square of `light_sampling`, turned and scaled twice
+        let mat: MaterialPtr = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let quad = Quad::new(
+            Point3::new(-0.5, -0.5, -100.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &mat,
+        );
+        let light = Animated::new(&quad, &[keyframe(0.0, [0.0, 0.0, 30.0], [2.0; 3])]);
+        assert!(light.is_sampleable());
+        assert!((solid_angle(&light, &Point3::zero()) / 4e-4 - 1.0).abs() < 0.001);
     }
 }
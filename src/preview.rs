@@ -0,0 +1,230 @@
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use terminal_size::{terminal_size, Width};
+
+use crate::color::{color_to_pixel, Color};
+use crate::film::Film;
+use crate::image::{PixelColor, PixelCoord};
+
+#[derive(Clone, Copy)]
+pub enum PreviewMode {
+    /// sixel if the terminal is known to support it, ansi otherwise
+    Auto,
+    /// truecolor half-block characters
+    Ansi,
+    Sixel,
+}
+
+impl FromStr for PreviewMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "ansi" => Ok(Self::Ansi),
+            "sixel" => Ok(Self::Sixel),
+            _ => Err(format!(
+                "unknown preview mode '{}' (expected auto, ansi or sixel)",
+                s
+            )),
+        }
+    }
+}
+
+/// guess sixel support from the environment, as querying the terminal
+/// would require switching it to raw mode
+fn terminal_supports_sixel() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    term.contains("sixel")
+        || ["mlterm", "foot", "contour", "yaft"]
+            .iter()
+            .any(|t| term.starts_with(t))
+        || ["WezTerm", "iTerm.app", "mintty"].contains(&program.as_str())
+}
+
+/// width of the terminal, which `COLUMNS` overrides
+fn terminal_columns() -> u32 {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .or_else(|| terminal_size().map(|(Width(w), _)| w as u32))
+        .unwrap_or(80)
+}
+
+/// Live preview of a film in the terminal, redrawn in place on every update.
+pub struct Preview {
+    sixel: bool,
+    /// terminal lines used by the last drawing
+    lines: usize,
+}
+
+impl Preview {
+    pub fn new(mode: PreviewMode) -> Self {
+        Self {
+            sixel: match mode {
+                PreviewMode::Auto => terminal_supports_sixel(),
+                PreviewMode::Ansi => false,
+                PreviewMode::Sixel => true,
+            },
+            lines: 0,
+        }
+    }
+
+    pub fn show(&mut self, film: &Film) {
+        let mut out = String::new();
+        if self.lines == 0 {
+            out.push('\n');
+        } else {
+            write!(out, "\r\x1b[{}A", self.lines).unwrap();
+        }
+
+        if self.sixel {
+            let width = film.width().clamp(1, 480);
+            let pixels = downscale(film, width);
+            let height = pixels.len() as u32 / width;
+            if self.lines == 0 {
+                // make room for the image, so that redrawing does not scroll
+                self.lines = height as usize / 12 + 1;
+                out.push_str(&"\n".repeat(self.lines));
+                write!(out, "\x1b[{}A", self.lines).unwrap();
+            }
+            out.push_str(&encode_sixel(&pixels, width, height));
+            out.push('\n');
+        } else {
+            let width = film.width().clamp(1, terminal_columns().max(1));
+            let pixels = downscale(film, width);
+            let height = pixels.len() as u32 / width;
+            self.lines = height.div_ceil(2) as usize;
+            out.push_str(&encode_ansi(&pixels, width, height));
+        }
+
+        print!("{}", out);
+        io::stdout().flush().unwrap();
+    }
+}
+
+/// Box-filters the film down to the given width, keeping the aspect ratio.
+/// Rows are returned top to bottom.
+fn downscale(film: &Film, width: u32) -> Vec<PixelColor> {
+    let scale = film.width() as f64 / width as f64;
+    let height = ((film.height() as f64 / scale) as u32).max(1);
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for j in 0..height {
+        let y0 = (j as f64 * scale) as u32;
+        let y1 = (((j + 1) as f64 * scale) as u32).clamp(y0 + 1, film.height());
+        for i in 0..width {
+            let x0 = (i as f64 * scale) as u32;
+            let x1 = (((i + 1) as f64 * scale) as u32).clamp(x0 + 1, film.width());
+            let mut c = Color::zero();
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    c += film.get_color(&PixelCoord {
+                        x,
+                        y: film.height() - 1 - y,
+                    });
+                }
+            }
+            let n = (x1 - x0) * (y1 - y0);
+            pixels.push(color_to_pixel(&c, n));
+        }
+    }
+    pixels
+}
+
+/// Two pixels per character: the upper half block is drawn with the top
+/// pixel as foreground and the bottom one as background.
+fn encode_ansi(pixels: &[PixelColor], width: u32, height: u32) -> String {
+    let mut out = String::new();
+    let w = width as usize;
+    for j in (0..height as usize).step_by(2) {
+        for i in 0..w {
+            let top = &pixels[j * w + i];
+            write!(out, "\x1b[38;2;{};{};{}m", top.r, top.g, top.b).unwrap();
+            if j + 1 < height as usize {
+                let bottom = &pixels[(j + 1) * w + i];
+                write!(out, "\x1b[48;2;{};{};{}m", bottom.r, bottom.g, bottom.b).unwrap();
+            } else {
+                out.push_str("\x1b[49m");
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Sixel image using a 6x6x6 color cube as palette.
+fn encode_sixel(pixels: &[PixelColor], width: u32, height: u32) -> String {
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    let index: Vec<u16> = pixels
+        .iter()
+        .map(|c| level(c.r) * 36 + level(c.g) * 6 + level(c.b))
+        .collect();
+
+    let mut out = String::new();
+    write!(out, "\x1bPq\"1;1;{};{}", width, height).unwrap();
+    for i in 0..216 {
+        let pct = |l: u16| l * 100 / 5;
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            pct(i / 36),
+            pct(i / 6 % 6),
+            pct(i % 6)
+        )
+        .unwrap();
+    }
+
+    let w = width as usize;
+    let h = height as usize;
+    for band in (0..h).step_by(6) {
+        let rows = (h - band).min(6);
+        let mut used = [false; 216];
+        for y in band..band + rows {
+            for x in 0..w {
+                used[index[y * w + x] as usize] = true;
+            }
+        }
+        for (color, _) in used.iter().enumerate().filter(|(_, u)| **u) {
+            write!(out, "#{}", color).unwrap();
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..w {
+                let mut bits = 0;
+                for k in 0..rows {
+                    if index[(band + k) * w + x] as usize == color {
+                        bits |= 1 << k;
+                    }
+                }
+                let c = char::from(63 + bits);
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((prev, n + 1)),
+                    Some((prev, n)) => {
+                        push_sixel_run(&mut out, prev, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((prev, n)) = run {
+                push_sixel_run(&mut out, prev, n);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixel_run(out: &mut String, c: char, n: usize) {
+    if n > 3 {
+        write!(out, "!{}{}", n, c).unwrap();
+    } else {
+        out.extend(std::iter::repeat_n(c, n));
+    }
+}
@@ -1,91 +1,546 @@
 use std::io::{self, Write};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-use crate::camera::Camera;
-use crate::color::{color_to_pixel, Color};
-use crate::image::Image;
-use crate::material::RayScatter;
-use crate::objects::{Hittable, RayHit, World};
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::film::Film;
+use crate::image::{Image, PixelCoord};
+use crate::objects::{HitRecord, Hittable, RayHit};
 use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::spectrum::Wavelengths;
 use crate::utils::{random, INFINITY};
-use crate::vec3::unit_vector;
+use crate::vec3::{dot, unit_vector, Vec3};
 
-fn ray_color(r: &Ray, object: &impl Hittable, depth: u32) -> Color {
+/// weight of a sample from a strategy of density `pdf` combined with one of
+/// density `other_pdf` by multiple importance sampling
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// values of the color `c` at the traced wavelengths, or `c` itself when
+/// tracing colors
+fn spectrum(c: Color, wavelengths: Option<Wavelengths>) -> Color {
+    match wavelengths {
+        Some(w) => w.upsample(&c),
+        None => c,
+    }
+}
+
+/// fraction of the light crossing the atmosphere of the scene over `distance`
+fn transmittance(scene: &Scene, distance: f64) -> f64 {
+    scene
+        .atmosphere
+        .as_ref()
+        .map_or(1.0, |a| a.transmittance(distance))
+}
+
+/// fraction of the light crossing `distance` within an object of
+/// absorption coefficients `medium`
+fn absorption(medium: Option<Color>, distance: f64) -> Color {
+    match medium {
+        Some(sigma_a) => Color::new(
+            (-sigma_a.x() * distance).exp(),
+            (-sigma_a.y() * distance).exp(),
+            (-sigma_a.z() * distance).exp(),
+        ),
+        None => Color::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// Light reaching a hit straight from a light picked at random, weighted
+/// against finding it by sampling the material towards `wo`. The hit lies
+/// within the absorbing `medium`, if any.
+fn direct_light(
+    r: &Ray,
+    rec: &HitRecord,
+    wo: &Vec3,
+    scene: &Scene,
+    wavelengths: Option<Wavelengths>,
+    medium: Option<Color>,
+) -> Color {
+    let world = &scene.world;
+    let direction = match world.random_light_direction(&rec.p, r.time()) {
+        Some(d) => d,
+        None => return Color::zero(),
+    };
+    let shadow_ray = Ray::new(rec.p, direction, r.time());
+    let wi = unit_vector(&direction);
+    let scattering_pdf = rec.mat.pdf(rec, &wi, wo);
+    let light_pdf = world.light_pdf_value(&rec.p, &direction, r.time());
+    if scattering_pdf <= 0.0 || light_pdf <= 0.0 {
+        return Color::zero();
+    }
+    // media glowing in between are found by `ray_color` alone, so look
+    // through them for the light and only let them dim it
+    match world.hit_light(&shadow_ray, 0.001, INFINITY) {
+        RayHit::Hit(light_rec) => {
+            let visibility = world.transmittance(&shadow_ray, 0.001, light_rec.t * (1.0 - 1e-6));
+            if visibility <= 0.0 {
+                return Color::zero();
+            }
+            let emitted = light_rec.mat.emitted(&light_rec)
+                * absorption(medium, light_rec.distance)
+                * (visibility * transmittance(scene, light_rec.distance));
+            spectrum(rec.mat.eval(rec, &wi, wo), wavelengths) * spectrum(emitted, wavelengths)
+                / light_pdf
+                * power_heuristic(light_pdf, scattering_pdf)
+        }
+        RayHit::NoHit => Color::zero(),
+    }
+}
+
+/// Light reaching a hit straight from the punctual lights, unless an object
+/// stands in between, dimmed by the media it crosses.
+fn punctual_light(
+    r: &Ray,
+    rec: &HitRecord,
+    wo: &Vec3,
+    scene: &Scene,
+    wavelengths: Option<Wavelengths>,
+    medium: Option<Color>,
+) -> Color {
+    let mut color = Color::zero();
+    for light in &scene.lights {
+        let sample = match light.sample(&rec.p) {
+            Some(s) => s,
+            None => continue,
+        };
+        let f = rec.mat.eval(rec, &sample.wi, wo);
+        if f.is_near_zero() {
+            continue;
+        }
+        let shadow_ray = Ray::new(rec.p, sample.wi, r.time());
+        let visibility =
+            scene
+                .world
+                .transmittance(&shadow_ray, 0.001, sample.distance * (1.0 - 1e-6));
+        if visibility > 0.0 {
+            let irradiance = sample.irradiance * absorption(medium, sample.distance);
+            color += spectrum(f, wavelengths)
+                * spectrum(irradiance, wavelengths)
+                * (visibility * transmittance(scene, sample.distance));
+        }
+    }
+    color
+}
+
+/// Radiance along the ray, at the `wavelengths` in spectral mode.
+/// `scattering_pdf` is the density with which a non delta lobe scattered
+/// it, to weight the lights it finds against `direct_light`. `medium` holds
+/// the absorption coefficients of the object the ray travels within, if any.
+fn ray_color(
+    r: &Ray,
+    scene: &Scene,
+    depth: u32,
+    scattering_pdf: Option<f64>,
+    wavelengths: Option<Wavelengths>,
+    medium: Option<Color>,
+) -> Color {
     if depth == 0 {
         return Color::zero();
     }
-    let ray_hit = object.hit(r, 0.001, INFINITY);
+    let world = &scene.world;
+    let mut ray_hit = world.hit(r, 0.001, INFINITY);
+    // the atmosphere may scatter the ray before it reaches the object
+    if let (Some(atmosphere), RayHit::Hit(rec)) = (&scene.atmosphere, &ray_hit) {
+        if let RayHit::Hit(scattering) = atmosphere.hit(r, rec.t) {
+            ray_hit = RayHit::Hit(scattering);
+        }
+    }
     match ray_hit {
-        RayHit::Hit(rec) => match rec.mat.scatter(r, &rec) {
-            RayScatter::Scatter(scattered) => {
-                scattered.attenuation * ray_color(&scattered.ray, object, depth - 1)
+        RayHit::Hit(mut rec) => {
+            rec.wavelength = wavelengths.map(|w| w.hero());
+            // leaving an absorbing object through its back face also tells
+            // the medium of a ray that started within it
+            let inside = if rec.front_face {
+                medium
+            } else {
+                rec.mat.absorption().or(medium)
+            };
+            let absorbed = spectrum(absorption(inside, rec.distance), wavelengths);
+            let mut color = spectrum(rec.mat.emitted(&rec), wavelengths);
+            // glowing media are not lights, and only found this way
+            if let Some(pdf) = scattering_pdf.filter(|_| rec.mat.is_emissive()) {
+                let light_pdf = world.light_pdf_value(&r.origin(), &r.direction(), r.time());
+                color *= power_heuristic(pdf, light_pdf);
+            }
+            // delta lobes are left out of the evaluation, so lights only
+            // reach the other ones
+            let wo = -unit_vector(&r.direction());
+            if !world.lights().is_empty() {
+                color += direct_light(r, &rec, &wo, scene, wavelengths, inside);
             }
-            RayScatter::NoScatter => Color::zero(),
-        },
-        RayHit::NoHit => {
-            // background
-            let unit_direction = unit_vector(&r.direction());
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+            color += punctual_light(r, &rec, &wo, scene, wavelengths, inside);
+            let color = match rec.mat.sample(&rec, &wo) {
+                Some(sample) if sample.pdf > 0.0 => {
+                    let scattered = Ray::new(rec.p, sample.wi, r.time());
+                    let pdf = (!sample.delta).then_some(sample.pdf);
+                    let mut weight = spectrum(sample.weight(), wavelengths);
+                    let mut wavelengths = wavelengths;
+                    if let Some(w) = wavelengths.as_mut().filter(|_| rec.mat.is_dispersive()) {
+                        w.terminate_secondary(&mut weight);
+                    }
+                    // crossing the surface of an absorbing object enters or
+                    // leaves it, nested objects are not tracked
+                    let medium = match rec.mat.absorption() {
+                        Some(_) if dot(&sample.wi, &rec.normal) >= 0.0 => inside,
+                        Some(sigma_a) if rec.front_face => Some(sigma_a),
+                        Some(_) => None,
+                        None => inside,
+                    };
+                    color
+                        + weight * ray_color(&scattered, scene, depth - 1, pdf, wavelengths, medium)
+                }
+                _ => color,
+            };
+            absorbed * color
+        }
+        // rays scattered by non delta lobes already sampled the light of
+        // the background
+        RayHit::NoHit if scattering_pdf.is_some() => {
+            spectrum(scene.background.color_without_light(r), wavelengths)
+        }
+        RayHit::NoHit => spectrum(scene.background.color(r), wavelengths),
+    }
+}
+
+/// Window of the frame in pixels, from `(x0, y0)` included to `(x1, y1)`
+/// excluded, with the origin at the top left corner.
+#[derive(Clone, Copy)]
+pub struct Region {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Region {
+    /// the whole frame
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    /// whether the region lies within a frame of the given size
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.x1 <= width && self.y1 <= height
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid region '{}' (expected x0,y0,x1,y1)", s);
+        let v = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|_| err()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match v[..] {
+            [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok(Self { x0, y0, x1, y1 }),
+            _ => Err(err()),
         }
     }
 }
 
-pub fn render(
-    world: &World,
-    camera: &Camera,
-    width: u16,
-    height: u16,
-    samples_per_pixel: u32,
-    max_depth: u32,
-    threads: u32,
-) -> Image {
-    let _img = Image::new(width, height);
-    let mut _img_it = _img.iter();
-    let img = Arc::new(Mutex::new(_img));
-    let img_it = Arc::new(Mutex::new(_img_it));
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub threads: u32,
+    /// number of progressive passes the samples are split into
+    pub passes: u32,
+    /// renders only this window of the frame, the film being the size of it
+    pub region: Option<Region>,
+    /// output passes of the first hit accumulated along the image
+    pub aovs: Vec<Aov>,
+    /// traces wavelengths rather than colors
+    pub spectral: bool,
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    render_progressive(scene, settings, |_| true).to_image()
+}
+
+/// Renders the scene in progressive passes, calling `on_pass` with the
+/// accumulated film after each of them. Rendering stops early when `on_pass`
+/// returns false.
+pub fn render_progressive(
+    scene: &Scene,
+    settings: &RenderSettings,
+    mut on_pass: impl FnMut(&Film) -> bool,
+) -> Film {
+    let region = settings
+        .region
+        .unwrap_or(Region::full(settings.width, settings.height));
+    // pixels within the filter radius of the region contribute to it too
+    let margin = (scene.filter.radius() - 0.5).ceil().max(0.0) as u32;
+    let sampled = Region {
+        x0: region.x0.saturating_sub(margin),
+        y0: region.y0.saturating_sub(margin),
+        x1: (region.x1 + margin).min(settings.width),
+        y1: (region.y1 + margin).min(settings.height),
+    };
+    let width = sampled.width();
+    let height = sampled.height();
+    // offsets of the sampled pixels within the frame and the film, with y
+    // going up
+    let x_offset = sampled.x0 as f64;
+    let y_offset = (settings.height - sampled.y1) as f64;
+    let film_x_offset = (region.x0 - sampled.x0) as f64;
+    let film_y_offset = (sampled.y1 - region.y1) as f64;
+    let passes = settings.passes.clamp(1, settings.samples_per_pixel.max(1));
+    let film = Arc::new(Mutex::new(Film::new(
+        region.width(),
+        region.height(),
+        &scene.filter,
+        &settings.aovs,
+    )));
     let print_lock = Arc::new(Mutex::new(0));
 
-    thread::scope(|s| {
-        for _ in 0..threads {
-            let img = Arc::clone(&img);
-            let img_it = Arc::clone(&img_it);
-            let print_lock = Arc::clone(&print_lock);
-            s.spawn(move || loop {
-                let p = {
-                    match img_it.lock().unwrap().next() {
-                        Some(v) => v,
-                        None => break,
+    for pass in 0..passes {
+        // spread the samples evenly among passes
+        let samples_per_pixel = settings.samples_per_pixel / passes
+            + u32::from(pass < settings.samples_per_pixel % passes);
+        // index of the next pixel to sample, from the top row down
+        let next_pixel = Arc::new(Mutex::new(0u64));
+
+        thread::scope(|s| {
+            for _ in 0..settings.threads {
+                let film = Arc::clone(&film);
+                let next_pixel = Arc::clone(&next_pixel);
+                let print_lock = Arc::clone(&print_lock);
+                s.spawn(move || loop {
+                    let p = {
+                        let mut next = next_pixel.lock().unwrap();
+                        if *next >= width as u64 * height as u64 {
+                            break;
+                        }
+                        let i = *next;
+                        *next += 1;
+                        PixelCoord {
+                            x: (i % width as u64) as u32,
+                            y: height - 1 - (i / width as u64) as u32,
+                        }
+                    };
+                    if p.x == 0 {
+                        let _lock = print_lock.lock().unwrap();
+                        let done = pass as f64 * height as f64 + (height - p.y) as f64;
+                        let perc = 100.0 * done / (passes as f64 * height as f64);
+                        print!("\rProgress: {:04.1}%", perc);
+                        io::stdout().flush().unwrap();
                     }
-                };
-                if p.x == 0 {
-                    let _lock = print_lock.lock().unwrap();
-                    let perc = 100.0 * (height - p.y) as f64 / height as f64;
-                    print!("\rProgress: {:04.1}%", perc);
-                    io::stdout().flush().unwrap();
-                }
-                let mut pixel_color = Color::zero();
-                for _ in 0..samples_per_pixel {
-                    let u = (p.x as f64 + random()) / (width - 1) as f64;
-                    let v = (p.y as f64 + random()) / (height - 1) as f64;
-                    let r = camera.get_ray(u, v);
-                    pixel_color += ray_color(&r, world, max_depth);
-                }
-                {
-                    img.lock()
-                        .unwrap()
-                        .set_color(&p, &color_to_pixel(&pixel_color, samples_per_pixel));
-                }
-            });
+                    let mut samples = Vec::with_capacity(samples_per_pixel as usize);
+                    let mut aov_values = vec![Color::zero(); settings.aovs.len()];
+                    for n in 0..samples_per_pixel {
+                        let x = p.x as f64 + random();
+                        let y = p.y as f64 + random();
+                        let u = (x_offset + x) / settings.width as f64;
+                        let v = (y_offset + y) / settings.height as f64;
+                        let color = match scene.camera.get_sample(u, v) {
+                            Some((r, weight)) => {
+                                if !settings.aovs.is_empty() {
+                                    let hit = scene.world.hit(&r, 0.001, INFINITY);
+                                    for (aov, value) in settings.aovs.iter().zip(&mut aov_values) {
+                                        if !aov.is_id() {
+                                            *value += aov.value(scene, &r, &hit);
+                                        } else if n == 0 {
+                                            *value = aov.value(scene, &r, &hit);
+                                        }
+                                    }
+                                }
+                                weight
+                                    * if settings.spectral {
+                                        let wavelengths = Wavelengths::sample();
+                                        let values = ray_color(
+                                            &r,
+                                            scene,
+                                            settings.max_depth,
+                                            None,
+                                            Some(wavelengths),
+                                            None,
+                                        );
+                                        wavelengths.to_rgb(&values)
+                                    } else {
+                                        ray_color(&r, scene, settings.max_depth, None, None, None)
+                                    }
+                            }
+                            None => Color::zero(),
+                        };
+                        samples.push((
+                            x - film_x_offset,
+                            y - film_y_offset,
+                            color * scene.exposure,
+                        ));
+                    }
+                    {
+                        let mut film = film.lock().unwrap();
+                        for (x, y, c) in &samples {
+                            film.add_sample(*x, *y, c);
+                        }
+                        // passes are not filtered, and only kept within the film
+                        let fx = p.x as i64 - film_x_offset as i64;
+                        let fy = p.y as i64 - film_y_offset as i64;
+                        if !settings.aovs.is_empty()
+                            && (0..region.width() as i64).contains(&fx)
+                            && (0..region.height() as i64).contains(&fy)
+                        {
+                            let p = PixelCoord {
+                                x: fx as u32,
+                                y: fy as u32,
+                            };
+                            film.add_aov_samples(&p, &aov_values, samples_per_pixel);
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut film = film.lock().unwrap();
+        film.add_samples_per_pixel(samples_per_pixel);
+        if !on_pass(&film) {
+            break;
         }
-    });
+    }
 
     print!("\rProgress: 100.0%");
 
     println!("\nDone!");
-    Arc::try_unwrap(img)
-        .unwrap_or_else(|_| panic!("Error when accessing image mutex"))
+    Arc::try_unwrap(film)
+        .unwrap_or_else(|_| panic!("Error when accessing film mutex"))
         .into_inner()
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ray_color;
+    use crate::aabb::Aabb;
+    use crate::color::Color;
+    use crate::material::HenyeyGreenstein;
+    use crate::medium::{Grid, VolumeGrid};
+    use crate::ray::Ray;
+    use crate::scene::{build_scene, Scene};
+    use crate::vec3::{Point3, Vec3};
+
+    /// a cloud of unit size, glowing when `hot`, under a light if `lit`
+    fn cloud(hot: bool, lit: bool) -> Scene {
+        let light = r#"{"type": "quad", "q": [-10.0, 1.5, -10.0], "u": [20.0, 0.0, 0.0],
+                        "v": [0.0, 0.0, 20.0], "material": "light"}"#;
+        let json = format!(
+            r#"{{
+                "materials": {{"light": {{"type": "diffuse_light", "emit": [1.0, 1.0, 1.0]}}}},
+                "world": [{}],
+                "camera": {{"lookfrom": [0.0, 0.0, -3.0], "lookat": [0.0, 0.0, 0.0],
+                            "vup": [0.0, 1.0, 0.0], "aspect_ratio": 1.0}},
+                "background": {{"type": "solid", "color": [0.0, 0.0, 0.0]}}
+            }}"#,
+            if lit { light } else { "" }
+        );
+        let mut scene = build_scene(&serde_json::from_str(&json).unwrap()).unwrap();
+        let phase = HenyeyGreenstein::new(Color::new(0.9, 0.9, 0.9), 0.0);
+        let temperature = hot.then(|| Grid::new([2, 2, 2], vec![3000.0; 8]));
+        scene.world.add(&VolumeGrid::new(
+            Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0)),
+            Grid::new([2, 2, 2], vec![2.0; 8]),
+            temperature,
+            6e-4,
+            &phase,
+        ));
+        scene
+    }
+
+    #[test]
+    fn glowing_media_are_not_lights() {
+        let r = Ray::new(Point3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let n = 100000;
+        let mean = |scene: &Scene| {
+            let mut sum = Color::zero();
+            for _ in 0..n {
+                sum += ray_color(&r, scene, 2, None, None, None);
+            }
+            sum / n as f64
+        };
+        // the light and the glow of the medium add up
+        let both = mean(&cloud(true, true));
+        let sum = mean(&cloud(true, false)) + mean(&cloud(false, true));
+        for (a, b) in [
+            (both.x(), sum.x()),
+            (both.y(), sum.y()),
+            (both.z(), sum.z()),
+        ] {
+            assert!((a - b).abs() < 0.025 * b, "{} != {}", a, b);
+        }
+    }
+
+    /// a clear sphere of radius 4 holding glass turning white light into
+    /// (0.5, 0.25, 1) over 2 units, around `inside` objects, under a white sky
+    fn glass(inside: &str) -> Scene {
+        let json = format!(
+            r#"{{
+                "materials": {{
+                    "glass": {{"type": "dielectric", "refraction": 1.0,
+                              "absorption": {{"color": [0.5, 0.25, 1.0], "distance": 2.0}}}},
+                    "light": {{"type": "diffuse_light", "emit": [1.0, 1.0, 1.0]}}
+                }},
+                "world": [{{"type": "sphere", "center": [0.0, 0.0, 0.0], "radius": 4.0,
+                           "material": "glass"}}{}],
+                "camera": {{"lookfrom": [0.0, 0.0, -10.0], "lookat": [0.0, 0.0, 0.0],
+                            "vup": [0.0, 1.0, 0.0], "aspect_ratio": 1.0}},
+                "background": {{"type": "solid", "color": [1.0, 1.0, 1.0]}}
+            }}"#,
+            inside
+        );
+        build_scene(&serde_json::from_str(&json).unwrap()).unwrap()
+    }
+
+    fn assert_color(c: Color, expected: [f64; 3]) {
+        for (a, b) in [
+            (c.x(), expected[0]),
+            (c.y(), expected[1]),
+            (c.z(), expected[2]),
+        ] {
+            assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn absorption_from_inside() {
+        // a camera inside the glass, whose rays are not of unit length
+        let r = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, 3.0), 0.0);
+        let color = ray_color(&r, &glass(""), 2, None, None, None);
+        assert_color(color, [0.25, 0.0625, 1.0]);
+    }
+
+    #[test]
+    fn objects_inside_glass_are_dimmed() {
+        // a light 3 units deep into the glass
+        let light = r#", {"type": "sphere", "center": [0.0, 0.0, 0.0], "radius": 1.0,
+                          "material": "light"}"#;
+        let r = Ray::new(Point3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let color = ray_color(&r, &glass(light), 3, None, None, None);
+        assert_color(color, [0.5f64.powf(1.5), 0.25f64.powf(1.5), 1.0]);
+    }
+}
@@ -1,64 +1,380 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::camera::{Camera, CameraDescription};
+use crate::background::{create_background, BackgroundDescription, BackgroundPtr};
+use crate::camera::{create_camera, ApertureShapeDescription, CameraDescription, CameraPtr};
+use crate::filter::{create_filter, FilterDescription, FilterPtr};
+use crate::light::{create_light, LightDescription, LightPtr};
 use crate::material::{create_material, MaterialDescription, MaterialPtr};
-use crate::objects::{create_object, ObjectDescription, World};
+use crate::medium::{Atmosphere, AtmosphereDescription};
+use crate::objects::{
+    create_object, Animated, Identified, KeyframeDescription, ObjectDescription, World,
+};
 
 pub struct Scene {
     pub world: World,
-    pub camera: Camera,
+    /// punctual lights, apart from the objects
+    pub lights: Vec<LightPtr>,
+    /// fog between the objects
+    pub atmosphere: Option<Atmosphere>,
+    /// materials in the order of their ids
+    pub materials: Vec<MaterialPtr>,
+    pub camera: CameraPtr,
+    pub background: BackgroundPtr,
+    pub filter: FilterPtr,
+    /// scale of the radiance reaching the film
+    pub exposure: f64,
 }
 
 #[derive(Serialize, Deserialize)]
-struct ObjectWithMaterialDescription {
-    material: String,
+pub struct ObjectWithMaterialDescription {
+    /// optional name, to refer to the object from the camera
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub material: String,
+    /// translation keyframes, for objects moving during the shutter interval
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keyframes: Vec<KeyframeDescription>,
     #[serde(flatten)]
-    desc: ObjectDescription,
+    pub desc: ObjectDescription,
 }
 
+/// Materials and objects shared through the `include` list of a scene.
 #[derive(Serialize, Deserialize)]
-struct SceneDescription {
-    materials: HashMap<String, MaterialDescription>,
-    world: Vec<ObjectWithMaterialDescription>,
-    camera: CameraDescription,
+pub struct IncludeDescription {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub world: Vec<ObjectWithMaterialDescription>,
 }
 
-pub fn parse_scene(filepath: &str) -> Result<Scene, String> {
+#[derive(Serialize, Deserialize)]
+pub struct SceneDescription {
+    /// json files with further materials and objects, relative to this file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub world: Vec<ObjectWithMaterialDescription>,
+    /// lights without geometry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightDescription>,
+    /// homogeneous medium filling the scene
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atmosphere: Option<AtmosphereDescription>,
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub film: FilmDescription,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct FilmDescription {
+    /// pixel reconstruction filter
+    #[serde(default)]
+    pub filter: FilterDescription,
+}
+
+fn read_json<T: for<'a> Deserialize<'a>>(filepath: &Path) -> Result<T, String> {
     // read file contents
     let contents = match fs::read_to_string(filepath) {
         Ok(data) => data,
-        Err(e) => return Err(e.to_string()),
+        Err(e) => return Err(format!("{}: {}", filepath.display(), e)),
     };
 
-    // parse entire scene description
-    let s: SceneDescription = match serde_json::from_str(&contents.to_owned()) {
-        Ok(v) => v,
-        Err(e) => return Err(e.to_string()),
-    };
+    // parse entire description
+    match serde_json::from_str(&contents) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("{}: {}", filepath.display(), e)),
+    }
+}
 
+/// Same file as `path` whichever way it was reached, to recognize includes.
+fn file_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// Merges the included files into the description, depth first. Definitions
+/// of the including file take precedence over the included ones. `stack`
+/// holds the files being included, to reject cycles, and `merged` those
+/// already merged, which files included along several paths are only once.
+fn resolve_includes(
+    filepath: &Path,
+    include: &[String],
+    materials: &mut BTreeMap<String, MaterialDescription>,
+    world: &mut Vec<ObjectWithMaterialDescription>,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<PathBuf>,
+    merged: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let dir = filepath.parent().unwrap_or(Path::new(""));
+    for inc in include {
+        let path = dir.join(inc);
+        let key = file_key(&path);
+        if stack.contains(&key) {
+            return Err(format!("{} is part of an include cycle", path.display()));
+        }
+        if merged.contains(&key) {
+            continue;
+        }
+        files.push(path.clone());
+        let mut d: IncludeDescription = read_json(&path)?;
+        resolve_material_files(&path, &mut d.materials, files);
+        resolve_world_files(&path, &mut d.world, files);
+        stack.push(key.clone());
+        let result = resolve_includes(
+            &path,
+            &d.include,
+            &mut d.materials,
+            &mut d.world,
+            files,
+            stack,
+            merged,
+        );
+        stack.pop();
+        result?;
+        merged.push(key);
+        for (key, value) in d.materials {
+            materials.entry(key).or_insert(value);
+        }
+        world.append(&mut d.world);
+    }
+    Ok(())
+}
+
+/// Makes `file` relative to the working directory rather than to the
+/// description `filepath` it appears in, and records it.
+fn resolve_file(filepath: &Path, file: &mut String, files: &mut Vec<PathBuf>) {
+    let dir = filepath.parent().unwrap_or(Path::new(""));
+    let path = dir.join(&*file);
+    *file = path.to_string_lossy().into_owned();
+    files.push(path);
+}
+
+/// Resolves the textures of the materials defined in `filepath`.
+fn resolve_material_files(
+    filepath: &Path,
+    materials: &mut BTreeMap<String, MaterialDescription>,
+    files: &mut Vec<PathBuf>,
+) {
+    for m in materials.values_mut() {
+        m.for_each_file(&mut |file| resolve_file(filepath, file, files));
+    }
+}
+
+/// Resolves the data files of the objects defined in `filepath`.
+fn resolve_world_files(
+    filepath: &Path,
+    world: &mut [ObjectWithMaterialDescription],
+    files: &mut Vec<PathBuf>,
+) {
+    for o in world {
+        o.desc
+            .for_each_file(&mut |file| resolve_file(filepath, file, files));
+    }
+}
+
+/// Makes the paths of the external resources of the scene relative to the
+/// working directory rather than to the scene file, and records them.
+fn resolve_resources(filepath: &Path, s: &mut SceneDescription, files: &mut Vec<PathBuf>) {
+    resolve_material_files(filepath, &mut s.materials, files);
+    resolve_world_files(filepath, &mut s.world, files);
+    if let Some(ApertureShapeDescription::Image { file }) = &mut s.camera.aperture_shape {
+        resolve_file(filepath, file, files);
+    }
+}
+
+/// Loads a scene description and its includes, recording in `files` every
+/// file that was read, even when loading fails.
+pub fn load_scene_description(
+    filepath: &str,
+    files: &mut Vec<PathBuf>,
+) -> Result<SceneDescription, String> {
+    let path = PathBuf::from(filepath);
+    files.push(path.clone());
+    let mut s: SceneDescription = read_json(&path)?;
+    // resources of the included materials are relative to their own files
+    resolve_resources(&path, &mut s, files);
+    let include = std::mem::take(&mut s.include);
+    resolve_includes(
+        &path,
+        &include,
+        &mut s.materials,
+        &mut s.world,
+        files,
+        &mut vec![file_key(&path)],
+        &mut vec![],
+    )?;
+    Ok(s)
+}
+
+pub fn parse_scene(filepath: &str) -> Result<Scene, String> {
+    let mut files = vec![];
+    let s = load_scene_description(filepath, &mut files)?;
+    build_scene(&s)
+}
+
+pub fn build_scene(s: &SceneDescription) -> Result<Scene, String> {
+    build_scene_at(s, 0.0)
+}
+
+/// Builds the scene as seen at the given time of the camera animation.
+pub fn build_scene_at(s: &SceneDescription, time: f64) -> Result<Scene, String> {
     // materials
-    let mut materials: HashMap<String, MaterialPtr> = HashMap::new();
+    let mut materials: BTreeMap<String, MaterialPtr> = BTreeMap::new();
     for (key, value) in &s.materials {
-        materials.insert(key.clone(), create_material(value));
+        let m = create_material(value).map_err(|e| format!("material '{}': {}", key, e))?;
+        materials.insert(key.clone(), m);
     }
 
     // world
     let mut world = World::new();
-    for obj in &s.world {
+    for (i, obj) in s.world.iter().enumerate() {
         let matptr = &materials.get(&obj.material);
         let m = match matptr {
             Some(v) => v,
             None => return Err(format!("material '{}' not defined", &obj.material)),
         };
-        world.add(&create_object(&obj.desc, m));
+        let mut o = create_object(&obj.desc, m)?;
+        if !obj.keyframes.is_empty() {
+            o = Animated::new(&o, &obj.keyframes);
+        }
+        o = Identified::new(&o, i as u32 + 1);
+        // glowing media cannot be sampled, and are only found by scattering
+        if m.is_emissive() && o.is_sampleable() {
+            world.add_light(&o);
+        }
+        match &obj.name {
+            Some(name) => world.add_named(name, &o)?,
+            None => world.add(&o),
+        }
     }
 
-    // camera
+    // background, which may bring a light
+    let background = create_background(&s.background);
+
+    // punctual lights
+    let mut lights: Vec<LightPtr> = s.lights.iter().map(create_light).collect();
+    lights.extend(background.light());
+
+    // camera, focused by probing the world
     let c = &s.camera;
-    let camera = Camera::from(c);
+    let camera = create_camera(c, time, &world)?;
+
+    // acceleration structure, valid for the whole shutter interval
+    let (shutter_open, shutter_close) = c.shutter();
+    world.build_bvh(time + shutter_open, time + shutter_close);
+
+    // film
+    let filter = create_filter(&s.film.filter);
+
+    Ok(Scene {
+        world,
+        lights,
+        atmosphere: s.atmosphere.as_ref().map(Atmosphere::from),
+        materials: materials.into_values().collect(),
+        camera,
+        background,
+        filter,
+        exposure: c.exposure(),
+    })
+}
+
+pub fn save_scene(filepath: &str, s: &SceneDescription) -> Result<(), String> {
+    let contents = match serde_json::to_string_pretty(s) {
+        Ok(v) => v,
+        Err(e) => return Err(e.to_string()),
+    };
+    match fs::write(filepath, contents) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{build_scene, load_scene_description};
+
+    /// writes the json `files` to a fresh directory, returning its path
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    const SCENE: &str = r#"{"include": ["b.json", "c.json"],
+        "camera": {"lookfrom": [0.0, 0.0, 1.0], "lookat": [0.0, 0.0, 0.0],
+                   "vup": [0.0, 1.0, 0.0], "aspect_ratio": 1.0}}"#;
+
+    #[test]
+    fn shared_includes_are_merged_once() {
+        let dir = write_files(
+            "rusty_rays_diamond_include",
+            &[
+                ("a.json", SCENE),
+                ("b.json", r#"{"include": ["shared.json"]}"#),
+                ("c.json", r#"{"include": ["./shared.json"]}"#),
+                (
+                    "shared.json",
+                    r#"{"materials": {"white": {"type": "lambertian", "albedo": [1.0, 1.0, 1.0]}},
+                        "world": [{"type": "sphere", "center": [0.0, 0.0, 0.0], "radius": 1.0,
+                                   "material": "white"}]}"#,
+                ),
+            ],
+        );
+        let mut files = vec![];
+        let s = load_scene_description(dir.join("a.json").to_str().unwrap(), &mut files).unwrap();
+        assert_eq!(s.world.len(), 1);
+        assert!(s.materials.contains_key("white"));
+    }
 
-    Ok(Scene { world, camera })
+    #[test]
+    fn include_cycles_are_rejected() {
+        let dir = write_files(
+            "rusty_rays_include_cycle",
+            &[
+                ("a.json", SCENE),
+                ("b.json", r#"{"include": ["c.json"]}"#),
+                ("c.json", r#"{"include": ["b.json"]}"#),
+            ],
+        );
+        let mut files = vec![];
+        let err = load_scene_description(dir.join("a.json").to_str().unwrap(), &mut files)
+            .err()
+            .unwrap();
+        assert!(err.contains("include cycle"), "{}", err);
+    }
+
+    #[test]
+    fn only_sampleable_objects_are_lights() {
+        // a glowing fog around a light
+        let s = serde_json::from_str(
+            r#"{"materials": {"light": {"type": "diffuse_light", "emit": [1.0, 1.0, 1.0]}},
+                "world": [
+                    {"type": "sphere", "center": [0.0, 0.0, 0.0], "radius": 0.5,
+                     "material": "light"},
+                    {"type": "constant_medium", "density": 0.1, "material": "light",
+                     "boundary": {"type": "sphere", "center": [0.0, 0.0, 0.0], "radius": 2.0}}
+                ],
+                "camera": {"lookfrom": [0.0, 0.0, 5.0], "lookat": [0.0, 0.0, 0.0],
+                           "vup": [0.0, 1.0, 0.0], "aspect_ratio": 1.0}}"#,
+        )
+        .unwrap();
+        let scene = build_scene(&s).unwrap();
+        assert_eq!(scene.world.lights().len(), 1);
+    }
 }
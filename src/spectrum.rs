@@ -0,0 +1,198 @@
+use crate::{
+    color::{Color, KCD_PER_M2},
+    utils::random,
+};
+
+/// range of visible wavelengths in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// piecewise gaussian, of widths `s1` below `mu` and `s2` above
+fn gaussian(x: f64, mu: f64, s1: f64, s2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { s1 } else { s2 };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 color matching functions at the wavelength `lambda` in
+/// nanometers, from the multi-lobe fit of Wyman et al. 2013
+pub fn cie_xyz(lambda: f64) -> [f64; 3] {
+    [
+        1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8),
+    ]
+}
+
+/// linear sRGB color of CIE XYZ tristimulus values
+pub fn xyz_to_rgb(xyz: &[f64; 3]) -> Color {
+    let [x, y, z] = *xyz;
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// spectral radiance of a black body at `temperature` in kelvin, in W/(m² sr)
+/// per nanometer at the wavelength `lambda` in nanometers
+pub fn planck(lambda: f64, temperature: f64) -> f64 {
+    const H: f64 = 6.62607015e-34;
+    const C: f64 = 2.99792458e8;
+    const K: f64 = 1.380649e-23;
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+    let l = lambda * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * K * temperature)).exp() - 1.0)) * 1e-9
+}
+
+/// Radiance of a black body at `temperature` in kelvin, in the units of the
+/// renderer: around 0.2 for embers at 1500 K, 10 at 2000 K, and blinding at
+/// the temperature of the sun.
+pub fn blackbody(temperature: f64) -> Color {
+    let mut xyz = [0.0; 3];
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let b = planck(lambda, temperature);
+        for (c, m) in xyz.iter_mut().zip(cie_xyz(lambda)) {
+            *c += b * m;
+        }
+        lambda += 1.0;
+    }
+    // luminous efficacy in lm/W, and cd/m² to the units of the renderer
+    xyz_to_rgb(&xyz) * (683.0 * KCD_PER_M2 / 1000.0)
+}
+
+/// Linear sRGB color of the spectrum of radiance 1 at every wavelength,
+/// summed over 1 nm steps, which `to_rgb` maps to white.
+const EQUAL_ENERGY_RGB: [f64; 3] = [128.3367, 101.5544, 97.1019];
+
+/// Inverse of the colors of the basis spectra of `upsample`, so that the
+/// colors of the spectra it builds are the ones it was given.
+const BASIS_TO_RGB_INVERSE: [[f64; 3]; 3] = [
+    [1.0261, -0.0222, -0.0040],
+    [0.0043, 0.9630, 0.0327],
+    [0.0249, 0.0342, 0.9409],
+];
+
+fn smoothstep(x: f64, edge0: f64, edge1: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Value at the wavelength `lambda` of a smooth spectrum of the linear sRGB
+/// color `c`, made of red, green and blue bands that add up to the constant
+/// spectrum, so that white stays flat and reflectances stay within [0, 1].
+pub fn upsample(c: &Color, lambda: f64) -> f64 {
+    let red = smoothstep(lambda, 570.0, 610.0);
+    let blue = 1.0 - smoothstep(lambda, 470.0, 510.0);
+    let basis = [red, 1.0 - red - blue, blue];
+    let m = BASIS_TO_RGB_INVERSE;
+    let value: f64 = (0..3)
+        .map(|i| (m[i][0] * c.x() + m[i][1] * c.y() + m[i][2] * c.z()) * basis[i])
+        .sum();
+    value.max(0.0)
+}
+
+/// Wavelengths in nanometers traced together by a path in spectral mode:
+/// the hero wavelength, picked uniformly over the visible range, and the
+/// ones a third and two thirds of the range further, wrapping around. Their
+/// values travel along the path in the components of a `Color`.
+#[derive(Clone, Copy)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    /// whether the path still follows the other wavelengths
+    secondary: bool,
+}
+
+impl Wavelengths {
+    pub fn sample() -> Self {
+        let u = random();
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        Self {
+            lambda: [0.0, 1.0, 2.0].map(|i| LAMBDA_MIN + (u + i / 3.0).fract() * range),
+            secondary: true,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// values at the wavelengths of the spectrum of the color `c`
+    pub fn upsample(&self, c: &Color) -> Color {
+        Color::new(
+            upsample(c, self.lambda[0]),
+            upsample(c, self.lambda[1]),
+            upsample(c, self.lambda[2]),
+        )
+    }
+
+    /// Keeps only the hero wavelength once the path depends on it, as
+    /// through dispersive glass, its value in `values` then standing for
+    /// all three.
+    pub fn terminate_secondary(&mut self, values: &mut Color) {
+        if self.secondary {
+            self.secondary = false;
+            *values = Color::new(3.0 * values.x(), 0.0, 0.0);
+        }
+    }
+
+    /// linear sRGB color of the spectrum estimated by the `values` at the
+    /// wavelengths
+    pub fn to_rgb(&self, values: &Color) -> Color {
+        let mut xyz = [0.0; 3];
+        for (lambda, value) in self.lambda.iter().zip([values.x(), values.y(), values.z()]) {
+            for (c, m) in xyz.iter_mut().zip(cie_xyz(*lambda)) {
+                *c += value * m;
+            }
+        }
+        let rgb = xyz_to_rgb(&xyz) * ((LAMBDA_MAX - LAMBDA_MIN) / 3.0);
+        Color::new(
+            rgb.x() / EQUAL_ENERGY_RGB[0],
+            rgb.y() / EQUAL_ENERGY_RGB[1],
+            rgb.z() / EQUAL_ENERGY_RGB[2],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blackbody, cie_xyz, upsample, xyz_to_rgb, EQUAL_ENERGY_RGB, LAMBDA_MIN};
+    use crate::color::{luminance, Color};
+
+    #[test]
+    fn blackbody_colors() {
+        // white at the temperature of daylight, red when cooler
+        let d65 = blackbody(6504.0);
+        assert!((d65.x() / d65.z() - 1.0).abs() < 0.1 && (d65.y() / d65.z() - 1.0).abs() < 0.1);
+        let ember = blackbody(1500.0);
+        assert!(ember.x() > 2.0 * ember.y() && ember.y() > ember.z());
+        // the surface of the sun, about 2e6 kcd/m²
+        let sun = luminance(&blackbody(5772.0)) * 40.0;
+        assert!(sun > 1.5e6 && sun < 2.5e6);
+    }
+
+    #[test]
+    fn upsampled_spectra_keep_their_color() {
+        for c in [
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.8, 0.1, 0.1),
+            Color::new(0.2, 0.7, 0.3),
+            Color::new(0.1, 0.2, 0.9),
+        ] {
+            let mut xyz = [0.0; 3];
+            for i in 0..471 {
+                let lambda = LAMBDA_MIN + i as f64;
+                for (x, m) in xyz.iter_mut().zip(cie_xyz(lambda)) {
+                    *x += upsample(&c, lambda) * m;
+                }
+            }
+            let rgb = xyz_to_rgb(&xyz);
+            assert!((rgb.x() / EQUAL_ENERGY_RGB[0] - c.x()).abs() < 0.01);
+            assert!((rgb.y() / EQUAL_ENERGY_RGB[1] - c.y()).abs() < 0.01);
+            assert!((rgb.z() / EQUAL_ENERGY_RGB[2] - c.z()).abs() < 0.01);
+        }
+    }
+}
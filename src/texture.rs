@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::Color,
+    image::{load_ppm, PixelCoord},
+    objects::HitRecord,
+    vec3::Point3,
+};
+
+/// Value of a material parameter varying over surfaces.
+pub trait Texture: Sync + Send {
+    /// value at the texture coordinates `(u, v)` and point `p` of a surface
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub type TexturePtr = Arc<dyn Texture>;
+
+/// value of the texture at a hit
+pub fn texture_value(texture: &TexturePtr, rec: &HitRecord) -> Color {
+    texture.value(rec.u, rec.v, &rec.p)
+}
+
+pub struct ConstantTexture {
+    color: Color,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> TexturePtr {
+        Arc::new(Self { color })
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// Image mapped over the texture coordinates, repeating beyond `[0, 1]`
+/// and filtered bilinearly.
+pub struct ImageTexture {
+    /// rows going up from the bottom
+    colors: Vec<Color>,
+    width: usize,
+    height: usize,
+}
+
+/// Channel of an image holding a single parameter, as packed by other tools.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    R,
+    G,
+    B,
+}
+
+impl ImageTexture {
+    /// Texture of the PPM image `file`. Colors are decoded with the gamma of
+    /// the images this renderer writes, while other parameters are stored
+    /// linearly, possibly in a single `channel`.
+    pub fn load(file: &str, color: bool, channel: Option<Channel>) -> Result<TexturePtr, String> {
+        let img = load_ppm(file)?;
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let decode = |c: u8| {
+            let x = c as f64 / 255.0;
+            if color {
+                x * x
+            } else {
+                x
+            }
+        };
+        let mut colors = Vec::with_capacity(width * height);
+        for y in 0..img.height() {
+            for x in 0..img.width() {
+                let c = img.get_color(&PixelCoord { x, y });
+                let c = Color::new(decode(c.r), decode(c.g), decode(c.b));
+                colors.push(match channel {
+                    None => c,
+                    Some(Channel::R) => Color::new(c.x(), c.x(), c.x()),
+                    Some(Channel::G) => Color::new(c.y(), c.y(), c.y()),
+                    Some(Channel::B) => Color::new(c.z(), c.z(), c.z()),
+                });
+            }
+        }
+        Ok(Arc::new(Self {
+            colors,
+            width,
+            height,
+        }))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.colors[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        self.texel(x0, y0) * ((1.0 - fx) * (1.0 - fy))
+            + self.texel(x0 + 1, y0) * (fx * (1.0 - fy))
+            + self.texel(x0, y0 + 1) * ((1.0 - fx) * fy)
+            + self.texel(x0 + 1, y0 + 1) * (fx * fy)
+    }
+}
+
+/// Solid checker board of cubes of side `scale` alternating two textures.
+pub struct CheckerTexture {
+    scale: f64,
+    even: TexturePtr,
+    odd: TexturePtr,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: TexturePtr, odd: TexturePtr) -> TexturePtr {
+        Arc::new(Self { scale, even, odd })
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// A parameter given as a number, a color or a texture.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureDescription {
+    Value(f64),
+    Color([f64; 3]),
+    Texture(TextureKindDescription),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TextureKindDescription {
+    #[serde(rename = "image")]
+    Image {
+        /// PPM image, relative to the scene file
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<Channel>,
+    },
+    #[serde(rename = "checker")]
+    Checker {
+        scale: f64,
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+    },
+}
+
+impl TextureDescription {
+    /// calls `f` on the path of every image of the texture
+    pub fn for_each_file(&mut self, f: &mut impl FnMut(&mut String)) {
+        match self {
+            TextureDescription::Texture(TextureKindDescription::Image { file, .. }) => f(file),
+            TextureDescription::Texture(TextureKindDescription::Checker { even, odd, .. }) => {
+                even.for_each_file(f);
+                odd.for_each_file(f);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Texture of a parameter, `color` telling whether it holds a color rather
+/// than a number.
+pub fn create_texture(desc: &TextureDescription, color: bool) -> Result<TexturePtr, String> {
+    Ok(match desc {
+        TextureDescription::Value(v) => ConstantTexture::new(Color::new(*v, *v, *v)),
+        TextureDescription::Color(c) => ConstantTexture::new(Color::new(c[0], c[1], c[2])),
+        TextureDescription::Texture(TextureKindDescription::Image { file, channel }) => {
+            ImageTexture::load(file, color, *channel)?
+        }
+        TextureDescription::Texture(TextureKindDescription::Checker { scale, even, odd }) => {
+            CheckerTexture::new(
+                *scale,
+                create_texture(even, color)?,
+                create_texture(odd, color)?,
+            )
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{ImageTexture, Texture};
+    use crate::color::Color;
+    use crate::image::{load_ppm, PixelCoord};
+    use crate::vec3::Point3;
+
+    #[test]
+    fn image_texture_wraps_and_interpolates() {
+        // black and white texels, centered at u = 0.25 and u = 0.75
+        let texture = ImageTexture {
+            colors: vec![Color::zero(), Color::new(1.0, 1.0, 1.0)],
+            width: 2,
+            height: 1,
+        };
+        let value = |u: f64| texture.value(u, 0.5, &Point3::zero()).x();
+        for (u, expected) in [
+            (0.25, 0.0),
+            (0.5, 0.5),
+            (0.625, 0.75),
+            (0.75, 1.0),
+            // across the seam, back to the first texel
+            (1.0, 0.5),
+            (1.25, 0.0),
+            (-0.25, 1.0),
+            (0.0, 0.5),
+        ] {
+            assert!(
+                (value(u) - expected).abs() < 1e-9,
+                "{} at u = {}",
+                value(u),
+                u
+            );
+        }
+    }
+
+    #[test]
+    fn image_texture_rows_go_up() {
+        // red on the top row of the file, blue on the bottom one
+        let name = std::env::temp_dir().join("rusty_rays_texture_rows.ppm");
+        let name = name.to_str().unwrap();
+        fs::write(name, "P3\n1 2\n255\n255 0 0\n0 0 255\n").unwrap();
+
+        let img = load_ppm(name).unwrap();
+        let top = img.get_color(&PixelCoord { x: 0, y: 1 });
+        assert_eq!((top.r, top.g, top.b), (255, 0, 0));
+
+        let texture = ImageTexture::load(name, false, None).unwrap();
+        let top = texture.value(0.5, 0.75, &Point3::zero());
+        let bottom = texture.value(0.5, 0.25, &Point3::zero());
+        assert_eq!((top.x(), top.z()), (1.0, 0.0));
+        assert_eq!((bottom.x(), bottom.z()), (0.0, 1.0));
+    }
+}
@@ -8,22 +8,10 @@ pub fn deg_to_rad(deg: f64) -> f64 {
     deg * PI / 180.0
 }
 
-static mut RNG: Option<rand::rngs::ThreadRng> = None;
-
 pub fn random() -> f64 {
-    unsafe {
-        if RNG.is_none() {
-            RNG = Some(rand::thread_rng());
-        }
-        RNG.as_mut().unwrap().gen::<f64>()
-    }
+    rand::thread_rng().gen::<f64>()
 }
 
 pub fn random_between(min: f64, max: f64) -> f64 {
-    unsafe {
-        if RNG.is_none() {
-            RNG = Some(rand::thread_rng());
-        }
-        RNG.as_mut().unwrap().gen::<f64>() * (max - min) + min
-    }
+    rand::thread_rng().gen::<f64>() * (max - min) + min
 }
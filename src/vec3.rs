@@ -1,4 +1,4 @@
-use crate::utils::{random, random_between};
+use crate::utils::{random, random_between, PI};
 use std::fmt::Display;
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
@@ -47,6 +47,22 @@ impl Vec3 {
         unit_vector(&Self::random_in_unit_sphere())
     }
 
+    /// uniform direction within the cone of unit `axis` whose half angle
+    /// has the cosine `cos_theta_max`
+    pub fn random_in_cone(axis: &Vec3, cos_theta_max: f64) -> Self {
+        let z = 1.0 + random() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random();
+        let sin_theta = (1.0 - z * z).sqrt();
+        let a = if axis.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(&cross(axis, &a));
+        let u = cross(axis, &v);
+        u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + *axis * z
+    }
+
     pub fn random_in_unit_disc() -> Self {
         loop {
             let p = Vec3::new(random_between(-1.0, 1.0), random_between(-1.0, 1.0), 0.0);
@@ -73,7 +89,7 @@ impl Vec3 {
     }
 
     pub fn length_squared(&self) -> f64 {
-        return self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2];
+        self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
     }
 
     pub fn is_near_zero(&self) -> bool {
@@ -189,7 +205,7 @@ impl Div<f64> for Vec3 {
 }
 
 pub fn dot(x: &Vec3, y: &Vec3) -> f64 {
-    return x.e[0] * y.e[0] + x.e[1] * y.e[1] + x.e[2] * y.e[2];
+    x.e[0] * y.e[0] + x.e[1] * y.e[1] + x.e[2] * y.e[2]
 }
 
 pub fn cross(x: &Vec3, y: &Vec3) -> Vec3 {
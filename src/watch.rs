@@ -0,0 +1,39 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Polls a set of files for changes of their modification time.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(files: &[PathBuf]) -> Self {
+        let mut w = Self { files: vec![] };
+        w.add(files);
+        w
+    }
+
+    /// Starts watching the given files, keeping the snapshot of the ones
+    /// that are already watched.
+    pub fn add(&mut self, files: &[PathBuf]) {
+        for f in files {
+            if !self.files.iter().any(|(p, _)| p == f) {
+                self.files.push((f.clone(), modified(f)));
+            }
+        }
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|(p, _)| p.clone()).collect()
+    }
+
+    /// Whether any file was modified, created or removed since it was added.
+    pub fn changed(&self) -> bool {
+        self.files.iter().any(|(p, t)| modified(p) != *t)
+    }
+}